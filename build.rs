@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::fs::File;
+use std::path::{ Path, PathBuf };
 
 fn main() {
     // set by cargo, build scripts should use this directory for output files
@@ -7,10 +8,30 @@ fn main() {
     // https://doc.rust-lang.org/nightly/cargo/reference/unstable.html#artifact-dependencies
     let kernel = PathBuf::from(std::env::var_os("CARGO_BIN_FILE_KERNEL_kernel").unwrap());
 
+    // pack the initrd directory into a tar archive that the bootloader loads as ramdisk
+    let ramdisk_path = out_dir.join("initrd.tar");
+    create_ramdisk(Path::new("initrd"), &ramdisk_path);
+    println!("cargo:rerun-if-changed=initrd");
+
     // create an UEFI disk image (optional)
     let uefi_path = out_dir.join("uefi.img");
-    bootloader::UefiBoot::new(&kernel).create_disk_image(&uefi_path).unwrap();
+    bootloader::UefiBoot::new(&kernel).set_ramdisk(&ramdisk_path).create_disk_image(&uefi_path).unwrap();
+
+    // create a BIOS disk image
+    let bios_path = out_dir.join("bios.img");
+    bootloader::BiosBoot::new(&kernel).set_ramdisk(&ramdisk_path).create_disk_image(&bios_path).unwrap();
 
     // pass the disk image paths as env variables to the `main.rs`
     println!("cargo:rustc-env=UEFI_PATH={}", uefi_path.display());
-}
\ No newline at end of file
+    println!("cargo:rustc-env=BIOS_PATH={}", bios_path.display());
+    println!("cargo:rustc-env=RAMDISK_PATH={}", ramdisk_path.display());
+    println!("cargo:rustc-env=KERNEL_PATH={}", kernel.display());
+}
+
+fn create_ramdisk(source_dir: &Path, ramdisk_path: &Path) {
+    let mut builder = tar::Builder::new(File::create(ramdisk_path).unwrap());
+    // leave out timestamps and owners so that the archive only changes with its content
+    builder.mode(tar::HeaderMode::Deterministic);
+    builder.append_dir_all(".", source_dir).unwrap();
+    builder.finish().unwrap();
+}
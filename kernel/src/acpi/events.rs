@@ -0,0 +1,143 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{ AtomicBool, Ordering };
+use core::task::{ Context, Poll };
+use acpi::platform::address::{ AccessSize, GenericAddress };
+use futures_util::task::AtomicWaker;
+use log::{ info, warn };
+use x2apic::ioapic::IrqFlags;
+use x86_64::instructions::port::Port;
+
+use crate::interrupts;
+use super::{ registers, power };
+
+// The System Control Interrupt signals ACPI events, this kernel only enables the power button
+// fixed event. Each PM1 event block holds a status register followed by an enable register.
+// refer to ACPI specification 4.8.3.1 PM1 Event Grouping
+const POWER_BUTTON: u64 = 1 << 8;
+// PM1 control register, set once the firmware handed the ACPI hardware to the OS
+const SCI_ENABLE: u64 = 1 << 0;
+const ACPI_ENABLE_POLLS: usize = 10_000_000;
+
+static POWER_BUTTON_PRESSED: AtomicBool = AtomicBool::new(false);
+static POWER_BUTTON_WAKER: AtomicWaker = AtomicWaker::new();
+
+// Switch the machine to ACPI mode, enable the power button event and route the SCI through the
+// I/O APIC. Needs the interrupt controllers to be initialized.
+pub fn init() {
+    let fixed_hardware = match power::fixed_hardware() {
+        Some(fixed_hardware) => fixed_hardware,
+        None => {
+            return;
+        }
+    };
+    if !enable_acpi_mode(fixed_hardware) {
+        warn!("Failed to switch to ACPI mode, power button events are not available");
+        return;
+    }
+    for event_block in fixed_hardware.pm1a_event.iter().chain(fixed_hardware.pm1b_event.iter()) {
+        let (status, enable) = split_event_block(event_block);
+        // the status bits are cleared by writing ones
+        registers::write(&status, POWER_BUTTON);
+        let enabled = registers::read(&enable).unwrap_or(0);
+        registers::write(&enable, enabled | POWER_BUTTON);
+    }
+
+    // the SCI is a shareable, level triggered, active low interrupt unless the MADT overrides it
+    let sci = fixed_hardware.sci_interrupt;
+    let flags = IrqFlags::LEVEL_TRIGGERED | IrqFlags::LOW_ACTIVE;
+    if let Err(error) = interrupts::register_isa_irq(sci as u8, flags, handle_sci) {
+        warn!("Failed to register the ACPI SCI on IRQ {sci}: {error:?}");
+        return;
+    }
+    info!("ACPI SCI on IRQ {sci} routed, power button enabled.");
+}
+
+// called on every SCI, the status bits have to be cleared before the end of interrupt
+fn handle_sci() -> bool {
+    let fixed_hardware = match power::fixed_hardware() {
+        Some(fixed_hardware) => fixed_hardware,
+        None => {
+            return false;
+        }
+    };
+    let mut handled = false;
+    for event_block in fixed_hardware.pm1a_event.iter().chain(fixed_hardware.pm1b_event.iter()) {
+        let (status, enable) = split_event_block(event_block);
+        let pending = registers::read(&status).unwrap_or(0) & registers::read(&enable).unwrap_or(0);
+        if pending == 0 {
+            continue;
+        }
+        registers::write(&status, pending);
+        handled = true;
+        if pending & POWER_BUTTON != 0 {
+            POWER_BUTTON_PRESSED.store(true, Ordering::Release);
+            POWER_BUTTON_WAKER.wake();
+        }
+    }
+    handled
+}
+
+// resolves once the power button was pressed
+pub fn power_button() -> PowerButton {
+    PowerButton { _private: () }
+}
+
+pub struct PowerButton {
+    _private: (),
+}
+
+impl Future for PowerButton {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if POWER_BUTTON_PRESSED.swap(false, Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        POWER_BUTTON_WAKER.register(cx.waker());
+        match POWER_BUTTON_PRESSED.swap(false, Ordering::Acquire) {
+            true => {
+                POWER_BUTTON_WAKER.take();
+                Poll::Ready(())
+            }
+            false => Poll::Pending,
+        }
+    }
+}
+
+fn enable_acpi_mode(fixed_hardware: &power::FixedHardware) -> bool {
+    let sci_enabled = || registers::read(&fixed_hardware.pm1a_control).unwrap_or(0) & SCI_ENABLE != 0;
+    if sci_enabled() {
+        return true;
+    }
+    // without an SMI command port the machine is always in ACPI mode
+    if fixed_hardware.smi_command_port == 0 || fixed_hardware.acpi_enable == 0 {
+        return true;
+    }
+    unsafe { Port::<u8>::new(fixed_hardware.smi_command_port as u16).write(fixed_hardware.acpi_enable) }
+    for _ in 0..ACPI_ENABLE_POLLS {
+        if sci_enabled() {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+// The status and enable registers are the two halves of an event block. An access size meant
+// for the whole block is narrowed to a half, it would touch the other register as well.
+pub fn split_event_block(event_block: &GenericAddress) -> (GenericAddress, GenericAddress) {
+    let half_width = event_block.bit_width / 2;
+    let access_size = match half_width {
+        // without an access size the width of each half is used
+        _ if event_block.access_size == AccessSize::Undefined => AccessSize::Undefined,
+        _ if registers::access_width(event_block) <= half_width => event_block.access_size,
+        8 => AccessSize::ByteAccess,
+        16 => AccessSize::WordAccess,
+        32 => AccessSize::DWordAccess,
+        _ => AccessSize::Undefined,
+    };
+    let status = GenericAddress { bit_width: half_width, access_size, ..*event_block };
+    let enable = GenericAddress { bit_width: half_width, access_size, address: event_block.address + (half_width as u64) / 8, ..*event_block };
+    (status, enable)
+}
@@ -0,0 +1,148 @@
+use alloc::{ boxed::Box, vec };
+use core::ptr::{ read_volatile, write_volatile };
+use core::slice;
+use acpi::{ AcpiHandler, AcpiTables, AmlTable };
+use aml::{ AmlContext, AmlName, AmlValue, DebugVerbosity, Handler };
+use aml::value::Args;
+use conquer_once::spin::OnceCell;
+use log::{ info, warn };
+use spinning_top::Spinlock;
+use x86_64::instructions::port::Port;
+
+use crate::memory;
+use super::registers;
+
+// The DSDT and SSDTs hold AML byte code describing the platform, parsed once at boot so that
+// objects like the `\_S5` sleep package can be looked up and methods like `\_PTS` called.
+static AML_CONTEXT: OnceCell<Spinlock<AmlContext>> = OnceCell::uninit();
+
+struct AmlHandler;
+
+impl AmlHandler {
+    unsafe fn read<T: Copy>(address: usize) -> T {
+        read_volatile(memory::physical_to_virtual(address as u64).as_ptr::<T>())
+    }
+    unsafe fn write<T: Copy>(address: usize, value: T) {
+        write_volatile(memory::physical_to_virtual(address as u64).as_mut_ptr::<T>(), value);
+    }
+}
+
+impl Handler for AmlHandler {
+    fn read_u8(&self, address: usize) -> u8 {
+        unsafe { Self::read(address) }
+    }
+    fn read_u16(&self, address: usize) -> u16 {
+        unsafe { Self::read(address) }
+    }
+    fn read_u32(&self, address: usize) -> u32 {
+        unsafe { Self::read(address) }
+    }
+    fn read_u64(&self, address: usize) -> u64 {
+        unsafe { Self::read(address) }
+    }
+
+    fn write_u8(&mut self, address: usize, value: u8) {
+        unsafe { Self::write(address, value) }
+    }
+    fn write_u16(&mut self, address: usize, value: u16) {
+        unsafe { Self::write(address, value) }
+    }
+    fn write_u32(&mut self, address: usize, value: u32) {
+        unsafe { Self::write(address, value) }
+    }
+    fn write_u64(&mut self, address: usize, value: u64) {
+        unsafe { Self::write(address, value) }
+    }
+
+    fn read_io_u8(&self, port: u16) -> u8 {
+        unsafe { Port::new(port).read() }
+    }
+    fn read_io_u16(&self, port: u16) -> u16 {
+        unsafe { Port::new(port).read() }
+    }
+    fn read_io_u32(&self, port: u16) -> u32 {
+        unsafe { Port::new(port).read() }
+    }
+
+    fn write_io_u8(&self, port: u16, value: u8) {
+        unsafe { Port::new(port).write(value) }
+    }
+    fn write_io_u16(&self, port: u16, value: u16) {
+        unsafe { Port::new(port).write(value) }
+    }
+    fn write_io_u32(&self, port: u16, value: u32) {
+        unsafe { Port::new(port).write(value) }
+    }
+
+    fn read_pci_u8(&self, _segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u8 {
+        (self.read_pci_u32(0, bus, device, function, offset & !0b11) >> ((offset & 0b11) * 8)) as u8
+    }
+    fn read_pci_u16(&self, _segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u16 {
+        (self.read_pci_u32(0, bus, device, function, offset & !0b11) >> ((offset & 0b10) * 8)) as u16
+    }
+    fn read_pci_u32(&self, _segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u32 {
+        unsafe { registers::read_pci_u32(bus, device, function, offset) }
+    }
+
+    fn write_pci_u8(&self, _segment: u16, bus: u8, device: u8, function: u8, offset: u16, value: u8) {
+        unsafe { registers::write_pci_u8(bus, device, function, offset, value) }
+    }
+    fn write_pci_u16(&self, _segment: u16, bus: u8, device: u8, function: u8, offset: u16, value: u16) {
+        unsafe { registers::write_pci_u16(bus, device, function, offset, value) }
+    }
+    fn write_pci_u32(&self, _segment: u16, bus: u8, device: u8, function: u8, offset: u16, value: u32) {
+        unsafe { registers::write_pci_u32(bus, device, function, offset, value) }
+    }
+}
+
+// Parse the DSDT and every SSDT. Objects are not initialized, running `_INI` methods is not
+// needed for the static packages the kernel looks up.
+pub fn init<H: AcpiHandler>(acpi_tables: &AcpiTables<H>) {
+    let mut context = AmlContext::new(Box::new(AmlHandler), DebugVerbosity::None);
+    let tables = acpi_tables.dsdt.iter().chain(acpi_tables.ssdts.iter());
+    for (index, table) in tables.enumerate() {
+        if let Err(error) = context.parse_table(aml_stream(table)) {
+            warn!("Failed to parse AML table {index}: {error:?}");
+        }
+    }
+    info!("ACPI Machine Language (AML) tables parsed.");
+    AML_CONTEXT.init_once(|| Spinlock::new(context));
+}
+
+// the `SLP_TYPa` and `SLP_TYPb` values for entering the sleep state `\_Sx`
+pub fn sleep_types(state: u8) -> Option<(u8, u8)> {
+    let path = AmlName::from_str(&alloc::format!("\\_S{state}_")).ok()?;
+    let context = AML_CONTEXT.get()?.lock();
+    let package = match context.namespace.get_by_path(&path) {
+        Ok(AmlValue::Package(package)) => package,
+        _ => {
+            return None;
+        }
+    };
+    let sleep_type_a = package.first()?.as_integer(&context).ok()?;
+    // some firmware only has a single element
+    let sleep_type_b = package.get(1).map_or(Ok(sleep_type_a), |value| value.as_integer(&context)).ok()?;
+    Some((sleep_type_a as u8, sleep_type_b as u8))
+}
+
+// tell the firmware that the system is about to enter a sleep state, the method is optional
+pub fn prepare_to_sleep(state: u8) {
+    let mut context = match AML_CONTEXT.get() {
+        Some(context) => context.lock(),
+        None => {
+            return;
+        }
+    };
+    let path = AmlName::from_str("\\_PTS").unwrap();
+    if context.namespace.get_by_path(&path).is_ok() {
+        let args = Args::from_list(vec![AmlValue::Integer(state as u64)]).unwrap();
+        if let Err(error) = context.invoke_method(&path, args) {
+            warn!("Failed to run \\_PTS: {error:?}");
+        }
+    }
+}
+
+fn aml_stream(table: &AmlTable) -> &'static [u8] {
+    let address = memory::physical_to_virtual(table.address as u64);
+    unsafe { slice::from_raw_parts(address.as_ptr(), table.length as usize) }
+}
@@ -0,0 +1,63 @@
+use core::ptr::NonNull;
+use acpi::{ AcpiTables, AcpiHandler, PhysicalMapping, HpetInfo, platform::{ interrupt::Apic, ProcessorInfo } };
+use acpi::{ fadt::Fadt, sdt::Signature };
+use x86_64::{ VirtAddr, structures::paging::Page };
+use log::{ info, warn };
+use crate::memory;
+
+mod registers;
+mod interpreter;
+mod power;
+mod events;
+
+pub use power::{ shutdown, reboot };
+pub use events::{ init as init_events, power_button, split_event_block, PowerButton };
+
+#[derive(Clone)]
+pub struct ACPIHandler;
+
+impl AcpiHandler for ACPIHandler {
+    unsafe fn map_physical_region<T>(&self, physical_address: usize, size: usize) -> PhysicalMapping<Self, T> {
+        let virtual_address = VirtAddr::new(physical_address as u64);
+        memory::identity_map(physical_address as u64, None);
+        PhysicalMapping::new(physical_address, NonNull::new(virtual_address.as_mut_ptr()).unwrap(), size, size, Self)
+    }
+
+    fn unmap_physical_region<T>(region: &PhysicalMapping<Self, T>) {
+        let virtual_address = VirtAddr::new(region.virtual_start().as_ptr() as u64);
+        let page: Page = Page::containing_address(virtual_address);
+        memory::unmap(page);
+    }
+}
+
+// the parts of the ACPI tables the kernel uses after boot
+pub struct AcpiInfo {
+    pub apic: Apic,
+    pub hpet: Option<HpetInfo>,
+    pub processors: ProcessorInfo,
+}
+
+// Root System Description Pointer
+pub fn init(rsdp_addr: u64) -> AcpiInfo {
+    let acpi_tables = unsafe { AcpiTables::from_rsdp(ACPIHandler, rsdp_addr as usize).expect("Failed to get ACPI Tables") };
+    let platform_info = acpi_tables.platform_info().unwrap();
+    let processor_info = platform_info.processor_info.expect("Failed to get processor info");
+    info!("Power Profile: {:?}", platform_info.power_profile);
+    info!("Boot Processor: {:?}", processor_info.boot_processor);
+    info!("Application Processors: {:?}", processor_info.application_processors);
+    let apic = match platform_info.interrupt_model {
+        acpi::InterruptModel::Apic(apic) => apic,
+        _ => {
+            panic!("Failed to get interrupt model from ACPI");
+        }
+    };
+    let hpet = HpetInfo::new(&acpi_tables).ok();
+    info!("HPET: {:?}", hpet.as_ref().map(|hpet| hpet.base_address));
+
+    interpreter::init(&acpi_tables);
+    match unsafe { acpi_tables.get_sdt::<Fadt>(Signature::FADT) } {
+        Ok(Some(fadt)) => power::init(&fadt),
+        _ => warn!("Failed to get the FADT, ACPI power management is not available"),
+    }
+    AcpiInfo { apic, hpet, processors: processor_info }
+}
\ No newline at end of file
@@ -0,0 +1,132 @@
+use acpi::fadt::Fadt;
+use acpi::platform::address::GenericAddress;
+use conquer_once::spin::OnceCell;
+use log::{ info, warn };
+use x86_64::instructions::{ interrupts, port::Port, tables::lidt };
+use x86_64::structures::DescriptorTablePointer;
+use x86_64::VirtAddr;
+
+use super::{ interpreter, registers };
+
+// PM1 control register
+// refer to ACPI specification 4.8.3.2.1 PM1 Control Registers
+const SLEEP_TYPE_SHIFT: u64 = 10;
+const SLEEP_TYPE_MASK: u64 = 0b111 << SLEEP_TYPE_SHIFT;
+const SLEEP_ENABLE: u64 = 1 << 13;
+const S5_SOFT_OFF: u8 = 5;
+// the 8042 keyboard controller can pulse the CPU reset line
+const KEYBOARD_CONTROLLER_COMMAND_PORT: u16 = 0x64;
+const KEYBOARD_CONTROLLER_INPUT_BUFFER_FULL: u8 = 1 << 1;
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xfe;
+// polls of the hardware before moving on to the next way of resetting
+const RESET_POLLS: usize = 1_000_000;
+
+static FIXED_HARDWARE: OnceCell<FixedHardware> = OnceCell::uninit();
+
+// the FADT registers needed to change the power state and to handle fixed events
+pub(super) struct FixedHardware {
+    pub pm1a_control: GenericAddress,
+    pub pm1b_control: Option<GenericAddress>,
+    pub pm1a_event: Option<GenericAddress>,
+    pub pm1b_event: Option<GenericAddress>,
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+    pub sci_interrupt: u16,
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+}
+
+pub fn init(fadt: &Fadt) {
+    let pm1a_control = match fadt.pm1a_control_block() {
+        Ok(pm1a_control) => pm1a_control,
+        Err(error) => {
+            warn!("FADT has no valid PM1a control block: {error:?}");
+            return;
+        }
+    };
+    // copied out of the packed table before calling methods on it
+    let flags = fadt.flags;
+    let reset_register = match flags.supports_system_reset_via_fadt() {
+        true => fadt.reset_register().ok(),
+        false => None,
+    };
+    FIXED_HARDWARE.init_once(|| FixedHardware {
+        pm1a_control,
+        pm1b_control: fadt.pm1b_control_block().ok().flatten(),
+        pm1a_event: fadt.pm1a_event_block().ok(),
+        pm1b_event: fadt.pm1b_event_block().ok().flatten(),
+        reset_register,
+        reset_value: fadt.reset_value,
+        sci_interrupt: fadt.sci_interrupt,
+        smi_command_port: fadt.smi_cmd_port,
+        acpi_enable: fadt.acpi_enable,
+    });
+}
+
+pub(super) fn fixed_hardware() -> Option<&'static FixedHardware> {
+    FIXED_HARDWARE.get()
+}
+
+// Power off through the ACPI S5 soft-off state, halts forever if that is not possible.
+pub fn shutdown() -> ! {
+    info!("Shutting down.");
+    interrupts::disable();
+    match (FIXED_HARDWARE.get(), interpreter::sleep_types(S5_SOFT_OFF)) {
+        (Some(fixed_hardware), Some((sleep_type_a, sleep_type_b))) => {
+            interpreter::prepare_to_sleep(S5_SOFT_OFF);
+            log::logger().flush();
+            enter_sleep_state(&fixed_hardware.pm1a_control, sleep_type_a);
+            if let Some(pm1b_control) = &fixed_hardware.pm1b_control {
+                enter_sleep_state(pm1b_control, sleep_type_b);
+            }
+            for _ in 0..RESET_POLLS {
+                core::hint::spin_loop();
+            }
+            warn!("The machine is still running after entering S5.");
+        }
+        _ => warn!("The ACPI S5 sleep state is not available."),
+    }
+    warn!("Failed to power off, halting.");
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+// Restart through the FADT reset register, then the keyboard controller, then a triple fault.
+pub fn reboot() -> ! {
+    info!("Rebooting.");
+    log::logger().flush();
+    interrupts::disable();
+    if let Some(fixed_hardware) = FIXED_HARDWARE.get() {
+        if let Some(reset_register) = &fixed_hardware.reset_register {
+            registers::write(reset_register, fixed_hardware.reset_value as u64);
+            for _ in 0..RESET_POLLS {
+                core::hint::spin_loop();
+            }
+        }
+    }
+    unsafe {
+        let mut command = Port::<u8>::new(KEYBOARD_CONTROLLER_COMMAND_PORT);
+        for _ in 0..RESET_POLLS {
+            if command.read() & KEYBOARD_CONTROLLER_INPUT_BUFFER_FULL == 0 {
+                command.write(KEYBOARD_CONTROLLER_RESET);
+                break;
+            }
+        }
+        for _ in 0..RESET_POLLS {
+            core::hint::spin_loop();
+        }
+        // without an IDT the breakpoint becomes a double fault and then a triple fault, which resets the CPU
+        lidt(&DescriptorTablePointer { limit: 0, base: VirtAddr::new(0) });
+        x86_64::instructions::interrupts::int3();
+    }
+    unreachable!("The CPU did not reset after a triple fault");
+}
+
+fn enter_sleep_state(pm1_control: &GenericAddress, sleep_type: u8) {
+    let value = registers::read(pm1_control).unwrap_or(0) & !(SLEEP_TYPE_MASK | SLEEP_ENABLE);
+    let value = value | ((sleep_type as u64) << SLEEP_TYPE_SHIFT) & SLEEP_TYPE_MASK;
+    // the sleep type has to be written before setting the enable bit
+    registers::write(pm1_control, value);
+    registers::write(pm1_control, value | SLEEP_ENABLE);
+}
@@ -0,0 +1,129 @@
+use core::ptr::{ read_volatile, write_volatile };
+use acpi::platform::address::{ AccessSize, AddressSpace, GenericAddress };
+use x86_64::instructions::port::Port;
+
+use crate::memory;
+
+// PCI configuration space access mechanism #1, only segment 0 is reachable through it
+// refer to https://wiki.osdev.org/PCI#Configuration_Space_Access_Mechanism_.231
+const PCI_CONFIG_ADDRESS_PORT: u16 = 0xcf8;
+const PCI_CONFIG_DATA_PORT: u16 = 0xcfc;
+
+// Read a register described by a Generic Address Structure, only system memory, system I/O
+// and PCI configuration space are supported.
+pub fn read(register: &GenericAddress) -> Option<u64> {
+    let width = access_width(register);
+    let value = unsafe {
+        match register.address_space {
+            AddressSpace::SystemMemory => {
+                let address = memory::physical_to_virtual(register.address);
+                match width {
+                    8 => read_volatile(address.as_ptr::<u8>()) as u64,
+                    16 => read_volatile(address.as_ptr::<u16>()) as u64,
+                    32 => read_volatile(address.as_ptr::<u32>()) as u64,
+                    _ => read_volatile(address.as_ptr::<u64>()),
+                }
+            }
+            AddressSpace::SystemIo => {
+                let port = register.address as u16;
+                match width {
+                    8 => Port::<u8>::new(port).read() as u64,
+                    16 => Port::<u16>::new(port).read() as u64,
+                    _ => Port::<u32>::new(port).read() as u64,
+                }
+            }
+            AddressSpace::PciConfigSpace => {
+                let (device, function, offset) = pci_address_parts(register.address);
+                let value = read_pci_u32(0, device, function, offset & !0b11) >> ((offset & 0b11) * 8);
+                (value as u64) & width_mask(width)
+            }
+            _ => {
+                return None;
+            }
+        }
+    };
+    let bits = if register.bit_width == 0 { width } else { register.bit_width };
+    Some((value >> register.bit_offset) & width_mask(bits))
+}
+
+// write a register described by a Generic Address Structure, returns false for unsupported address spaces
+pub fn write(register: &GenericAddress, value: u64) -> bool {
+    let width = access_width(register);
+    let value = value << register.bit_offset;
+    unsafe {
+        match register.address_space {
+            AddressSpace::SystemMemory => {
+                let address = memory::physical_to_virtual(register.address);
+                match width {
+                    8 => write_volatile(address.as_mut_ptr::<u8>(), value as u8),
+                    16 => write_volatile(address.as_mut_ptr::<u16>(), value as u16),
+                    32 => write_volatile(address.as_mut_ptr::<u32>(), value as u32),
+                    _ => write_volatile(address.as_mut_ptr::<u64>(), value),
+                }
+            }
+            AddressSpace::SystemIo => {
+                let port = register.address as u16;
+                match width {
+                    8 => Port::<u8>::new(port).write(value as u8),
+                    16 => Port::<u16>::new(port).write(value as u16),
+                    _ => Port::<u32>::new(port).write(value as u32),
+                }
+            }
+            AddressSpace::PciConfigSpace => {
+                let (device, function, offset) = pci_address_parts(register.address);
+                match width {
+                    8 => write_pci_u8(0, device, function, offset, value as u8),
+                    16 => write_pci_u16(0, device, function, offset, value as u16),
+                    _ => write_pci_u32(0, device, function, offset, value as u32),
+                }
+            }
+            _ => {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+pub unsafe fn read_pci_u32(bus: u8, device: u8, function: u8, offset: u16) -> u32 {
+    Port::<u32>::new(PCI_CONFIG_ADDRESS_PORT).write(pci_config_address(bus, device, function, offset));
+    Port::<u32>::new(PCI_CONFIG_DATA_PORT).read()
+}
+
+pub unsafe fn write_pci_u8(bus: u8, device: u8, function: u8, offset: u16, value: u8) {
+    Port::<u32>::new(PCI_CONFIG_ADDRESS_PORT).write(pci_config_address(bus, device, function, offset));
+    Port::<u8>::new(PCI_CONFIG_DATA_PORT + (offset & 0b11)).write(value);
+}
+
+pub unsafe fn write_pci_u16(bus: u8, device: u8, function: u8, offset: u16, value: u16) {
+    Port::<u32>::new(PCI_CONFIG_ADDRESS_PORT).write(pci_config_address(bus, device, function, offset));
+    Port::<u16>::new(PCI_CONFIG_DATA_PORT + (offset & 0b10)).write(value);
+}
+
+pub unsafe fn write_pci_u32(bus: u8, device: u8, function: u8, offset: u16, value: u32) {
+    Port::<u32>::new(PCI_CONFIG_ADDRESS_PORT).write(pci_config_address(bus, device, function, offset));
+    Port::<u32>::new(PCI_CONFIG_DATA_PORT).write(value);
+}
+
+fn pci_config_address(bus: u8, device: u8, function: u8, offset: u16) -> u32 {
+    1 << 31 | (bus as u32) << 16 | ((device as u32) & 0x1f) << 11 | ((function as u32) & 0x7) << 8 | ((offset as u32) & 0xfc)
+}
+
+// PCI addresses in a GAS are `device << 32 | function << 16 | offset` on bus 0
+fn pci_address_parts(address: u64) -> (u8, u8, u16) {
+    ((address >> 32) as u8, (address >> 16) as u8, address as u16)
+}
+
+pub(super) fn access_width(register: &GenericAddress) -> u8 {
+    match register.access_size {
+        AccessSize::ByteAccess => 8,
+        AccessSize::WordAccess => 16,
+        AccessSize::DWordAccess => 32,
+        AccessSize::QWordAccess => 64,
+        AccessSize::Undefined => register.bit_width.clamp(8, 64).next_power_of_two(),
+    }
+}
+
+fn width_mask(width: u8) -> u64 {
+    if width >= 64 { u64::MAX } else { (1 << width) - 1 }
+}
@@ -0,0 +1,83 @@
+use alloc::alloc::Layout;
+use core::ptr::NonNull;
+use core::sync::atomic::{ AtomicUsize, Ordering };
+use x86_64::VirtAddr;
+
+use linked_list_allocator::Heap;
+use spinning_top::Spinlock;
+use crate::memory;
+use super::{ heap_flags, align_up, HEAP_MAX_SIZE, PAGE_SIZE };
+
+// the heap grows by at least this much at a time to avoid mapping single pages over and over
+const HEAP_GROW_SIZE: usize = 64 * 1024; //64 KiB
+
+// A linked list heap that maps more pages on demand instead of failing when it runs out of
+// memory. It grows by at least `HEAP_GROW_SIZE` at a time but its size, mapped rather than
+// used bytes, never exceeds `limit` rounded down to a page. An allocation that does not fit
+// into what is left below that fails, even while less than `limit` bytes are in use.
+pub struct GrowableHeap {
+    heap: Spinlock<Heap>,
+    limit: AtomicUsize,
+}
+
+impl GrowableHeap {
+    pub const fn new() -> Self {
+        GrowableHeap {
+            heap: Spinlock::new(Heap::empty()),
+            limit: AtomicUsize::new(HEAP_MAX_SIZE),
+        }
+    }
+
+    pub unsafe fn init(&self, heap_start: VirtAddr, heap_size: usize) {
+        self.heap.lock().init(heap_start.as_mut_ptr(), heap_size);
+    }
+
+    pub fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let mut heap = self.heap.lock();
+        if let Ok(allocation) = heap.allocate_first_fit(layout) {
+            return Some(allocation);
+        }
+        if !self.grow(&mut heap, layout) {
+            return None;
+        }
+        heap.allocate_first_fit(layout).ok()
+    }
+
+    pub unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.heap.lock().deallocate(ptr, layout);
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+    pub fn size(&self) -> usize {
+        self.heap.lock().size()
+    }
+    pub fn used(&self) -> usize {
+        self.heap.lock().used()
+    }
+
+    // map enough pages after the current top of the heap to fit `layout`
+    fn grow(&self, heap: &mut Heap, layout: Layout) -> bool {
+        let needed = layout.size() + layout.align();
+        let grow_size = align_up(needed.max(HEAP_GROW_SIZE), PAGE_SIZE);
+        // the top of the heap has to stay page aligned, a limit inside a page is rounded down
+        let available = self.limit().saturating_sub(heap.size()) & !(PAGE_SIZE - 1);
+        let grow_size = grow_size.min(available);
+        if grow_size == 0 || grow_size < needed {
+            return false;
+        }
+        let heap_top = VirtAddr::from_ptr(heap.top());
+        // out of physical frames, the allocation fails and `handle_alloc_error` reports it
+        if memory::range_map(heap_top, grow_size as u64, Some(heap_flags())).is_err() {
+            return false;
+        }
+        unsafe {
+            heap.extend(grow_size);
+        }
+        true
+    }
+}
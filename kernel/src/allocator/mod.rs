@@ -0,0 +1,77 @@
+use alloc::alloc::Layout;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+use crate::{ memory, cmdline };
+
+mod heap;
+mod slab;
+
+pub use slab::SlabStats;
+use slab::SlabAllocator;
+
+#[global_allocator]
+static ALLOCATOR: SlabAllocator = SlabAllocator::new();
+
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+pub const HEAP_SIZE: usize = 100 * 1024; //100 KiB
+// default ceiling the heap may grow to, can be changed with `set_heap_limit`
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024; //64 MiB
+const PAGE_SIZE: usize = 4096;
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    panic!("Allocation error: {layout:?}, heap size: {} bytes, heap limit: {} bytes", heap_size(), heap_limit())
+}
+
+pub fn init_heap() {
+    let heap_start = VirtAddr::new(HEAP_START as u64);
+    memory::range_map(heap_start, HEAP_SIZE as u64, Some(heap_flags())).expect("Failed to map the heap");
+    unsafe {
+        ALLOCATOR.heap().init(heap_start, HEAP_SIZE);
+    }
+    if let Some(limit) = cmdline::cmdline().heap_limit {
+        set_heap_limit(limit);
+    }
+}
+
+// The heap never shrinks, a limit below the current size only stops further growth. The heap
+// grows in whole pages, a limit inside a page is rounded down.
+pub fn set_heap_limit(limit: usize) {
+    ALLOCATOR.heap().set_limit(limit);
+}
+pub fn heap_limit() -> usize {
+    ALLOCATOR.heap().limit()
+}
+pub fn heap_size() -> usize {
+    ALLOCATOR.heap().size()
+}
+pub fn heap_used() -> usize {
+    ALLOCATOR.heap().used()
+}
+pub fn slab_stats() -> [SlabStats; slab::BLOCK_SIZES.len()] {
+    ALLOCATOR.stats()
+}
+
+pub fn print_stats() {
+    println!("Heap: {} of {} bytes used, limit {} bytes", heap_used(), heap_size(), heap_limit());
+    for stats in slab_stats() {
+        println!(
+            "Slab {:>4} B: {} slabs, {} used, {} free, {} allocations, {} deallocations",
+            stats.block_size,
+            stats.slabs,
+            stats.used_blocks,
+            stats.free_blocks,
+            stats.allocations,
+            stats.deallocations
+        );
+    }
+}
+
+fn heap_flags() -> PageTableFlags {
+    PageTableFlags::PRESENT | PageTableFlags::WRITABLE
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
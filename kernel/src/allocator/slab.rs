@@ -0,0 +1,150 @@
+use alloc::alloc::{ GlobalAlloc, Layout };
+use core::ptr::{ self, NonNull };
+use spinning_top::Spinlock;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::heap::GrowableHeap;
+use super::PAGE_SIZE;
+
+// block sizes must be powers of two so that every block is aligned to its own size
+pub const BLOCK_SIZES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+// a slab always holds at least this many blocks
+const MIN_BLOCKS_PER_SLAB: usize = 8;
+
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+struct SlabClass {
+    free_list: Option<NonNull<FreeBlock>>,
+    stats: SlabStats,
+}
+
+unsafe impl Send for SlabClass {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    pub block_size: usize,
+    pub slabs: usize,
+    pub used_blocks: usize,
+    pub free_blocks: usize,
+    pub allocations: u64,
+    pub deallocations: u64,
+}
+
+impl SlabClass {
+    const fn new() -> Self {
+        SlabClass {
+            free_list: None,
+            stats: SlabStats { block_size: 0, slabs: 0, used_blocks: 0, free_blocks: 0, allocations: 0, deallocations: 0 },
+        }
+    }
+
+    fn pop(&mut self) -> Option<NonNull<u8>> {
+        let block = self.free_list?;
+        self.free_list = unsafe { block.as_ref().next };
+        self.stats.free_blocks -= 1;
+        self.stats.used_blocks += 1;
+        self.stats.allocations += 1;
+        Some(block.cast())
+    }
+
+    unsafe fn push(&mut self, ptr: NonNull<u8>) {
+        let block = ptr.cast::<FreeBlock>();
+        block.as_ptr().write(FreeBlock { next: self.free_list });
+        self.free_list = Some(block);
+        self.stats.free_blocks += 1;
+    }
+
+    // carve a new slab into blocks and put all of them on the free list
+    unsafe fn add_slab(&mut self, slab: NonNull<u8>, slab_size: usize) {
+        let block_size = self.stats.block_size;
+        for offset in (0..slab_size).step_by(block_size).rev() {
+            self.push(NonNull::new_unchecked(slab.as_ptr().add(offset)));
+        }
+        self.stats.slabs += 1;
+    }
+}
+
+// Size class front-end for the kernel heap: small allocations are served from
+// per-class free lists refilled one slab at a time, everything larger than the
+// biggest class goes straight to the growable linked list heap.
+// Slabs are never handed back to the heap.
+pub struct SlabAllocator {
+    classes: [Spinlock<SlabClass>; BLOCK_SIZES.len()],
+    heap: GrowableHeap,
+}
+
+impl SlabAllocator {
+    pub const fn new() -> Self {
+        SlabAllocator {
+            classes: [const { Spinlock::new(SlabClass::new()) }; BLOCK_SIZES.len()],
+            heap: GrowableHeap::new(),
+        }
+    }
+
+    pub fn heap(&self) -> &GrowableHeap {
+        &self.heap
+    }
+
+    pub fn stats(&self) -> [SlabStats; BLOCK_SIZES.len()] {
+        let mut stats = [SlabClass::new().stats; BLOCK_SIZES.len()];
+        for (index, class) in self.classes.iter().enumerate() {
+            stats[index] = class.lock().stats;
+            stats[index].block_size = BLOCK_SIZES[index];
+        }
+        stats
+    }
+
+    fn refill(&self, class: &mut SlabClass, index: usize) -> bool {
+        let block_size = BLOCK_SIZES[index];
+        let slab_size = (block_size * MIN_BLOCKS_PER_SLAB).max(PAGE_SIZE);
+        let layout = Layout::from_size_align(slab_size, PAGE_SIZE).unwrap();
+        match self.heap.allocate(layout) {
+            Some(slab) => {
+                class.stats.block_size = block_size;
+                unsafe { class.add_slab(slab, slab_size) };
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Interrupts are disabled while the locks are held, so that interrupt handlers can allocate
+// without deadlocking against the code they interrupted.
+unsafe impl GlobalAlloc for SlabAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        without_interrupts(|| {
+            let index = match class_index(layout) {
+                Some(index) => index,
+                None => {
+                    return self.heap.allocate(layout).map_or(ptr::null_mut(), |allocation| allocation.as_ptr());
+                }
+            };
+            let mut class = self.classes[index].lock();
+            if class.free_list.is_none() && !self.refill(&mut class, index) {
+                return ptr::null_mut();
+            }
+            class.pop().map_or(ptr::null_mut(), |block| block.as_ptr())
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let ptr = NonNull::new_unchecked(ptr);
+        without_interrupts(|| match class_index(layout) {
+            Some(index) => {
+                let mut class = self.classes[index].lock();
+                class.push(ptr);
+                class.stats.used_blocks -= 1;
+                class.stats.deallocations += 1;
+            }
+            None => self.heap.deallocate(ptr, layout),
+        })
+    }
+}
+
+fn class_index(layout: Layout) -> Option<usize> {
+    let required = layout.size().max(layout.align());
+    BLOCK_SIZES.iter().position(|&block_size| block_size >= required)
+}
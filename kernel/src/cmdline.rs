@@ -0,0 +1,157 @@
+use conquer_once::spin::OnceCell;
+use log::LevelFilter;
+
+use crate::time::ClockSource;
+
+// path of the kernel command line inside the ramdisk, the host runner replaces it with `--cmdline`
+pub const CMDLINE_PATH: &str = "cmdline";
+
+static CMDLINE: OnceCell<Cmdline> = OnceCell::uninit();
+static DEFAULT_CMDLINE: Cmdline = Cmdline::empty();
+
+// The kernel command line is a whitespace separated list of `key=value` options and `flag`s,
+// lines starting with `#` are comments. Known options are parsed into typed fields, anything
+// else can still be looked up with `get`.
+//
+//   log=<level>                 default log level: off, error, warn, info, debug or trace
+//   log.<module>=<level>        log level for a module and its submodules, e.g. log.kernel::task=debug
+//   heap_limit=<size>           ceiling of the kernel heap, e.g. 32M, suffixes K, M and G
+//   console_color=<r>,<g>,<b>   text color of the frame buffer console
+//   tasks=<name>,<name>         tasks started by the kernel, e.g. tasks=keyboard
+//   mouse=<on|off>              whether the PS/2 mouse is enabled
+//   timer_hz=<n>                timer interrupts per second, defaults to 1000
+//   tsc_deadline=<on|off>       prefer the TSC-deadline mode of the local APIC timer when supported
+//   clock=<apic|hpet>           clock source of the kernel uptime, defaults to apic
+//   tsc=<on|off>                whether the TSC is used for high resolution time, defaults to on if it is invariant
+//   smp=<on|off>                whether the application processors are started
+pub struct Cmdline {
+    text: &'static str,
+    pub log_level: LevelFilter,
+    pub heap_limit: Option<usize>,
+    pub console_color: Option<[u8; 3]>,
+    pub tasks: Option<&'static str>,
+    pub mouse: bool,
+    pub timer_hz: u32,
+    pub tsc_deadline: bool,
+    pub clock: ClockSource,
+    pub tsc: Option<bool>,
+    pub smp: bool,
+}
+
+impl Cmdline {
+    const fn empty() -> Self {
+        Cmdline {
+            text: "",
+            log_level: LevelFilter::Info,
+            heap_limit: None,
+            console_color: None,
+            tasks: None,
+            mouse: true,
+            timer_hz: 1000,
+            tsc_deadline: false,
+            clock: ClockSource::ApicTimer,
+            tsc: None,
+            smp: true,
+        }
+    }
+
+    pub fn parse(text: &'static str) -> Cmdline {
+        let mut cmdline = Cmdline { text, ..Cmdline::empty() };
+        for (key, value) in parse_options(text) {
+            let valid = match key {
+                "log" => value.parse().map(|level| cmdline.log_level = level).is_ok(),
+                "heap_limit" => parse_size(value).map(|size| cmdline.heap_limit = Some(size)).is_some(),
+                "console_color" => parse_color(value).map(|color| cmdline.console_color = Some(color)).is_some(),
+                "tasks" => {
+                    cmdline.tasks = Some(value);
+                    true
+                }
+                "mouse" => parse_bool(value).map(|mouse| cmdline.mouse = mouse).is_some(),
+                "timer_hz" => value.parse().ok().filter(|&hz| hz > 0).map(|hz| cmdline.timer_hz = hz).is_some(),
+                "tsc_deadline" => parse_bool(value).map(|tsc_deadline| cmdline.tsc_deadline = tsc_deadline).is_some(),
+                "clock" => ClockSource::from_name(value).map(|clock| cmdline.clock = clock).is_some(),
+                "tsc" => parse_bool(value).map(|tsc| cmdline.tsc = Some(tsc)).is_some(),
+                "smp" => parse_bool(value).map(|smp| cmdline.smp = smp).is_some(),
+                _ => true,
+            };
+            if !valid {
+                log::warn!("Invalid value for kernel command line option {key}: {value}");
+            }
+        }
+        cmdline
+    }
+
+    // all `key=value` pairs in order, flags have an empty value
+    pub fn options(&self) -> impl Iterator<Item = (&'static str, &'static str)> {
+        parse_options(self.text)
+    }
+
+    // the last occurrence of an option wins
+    pub fn get(&self, key: &str) -> Option<&'static str> {
+        self.options()
+            .filter(|(option, _)| *option == key)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    pub fn flag(&self, key: &str) -> bool {
+        self.get(key).is_some_and(|value| parse_bool(value).unwrap_or(true))
+    }
+
+    // per module log levels given as `log.<module>=<level>`
+    pub fn module_log_levels(&self) -> impl Iterator<Item = (&'static str, LevelFilter)> {
+        self.options().filter_map(|(key, value)| Some((key.strip_prefix("log.")?, value.parse().ok()?)))
+    }
+
+    // without a `tasks` option every task is started
+    pub fn task_enabled(&self, name: &str) -> bool {
+        self.tasks.is_none_or(|tasks| tasks.split(',').any(|task| task == name))
+    }
+
+    pub fn text(&self) -> &'static str {
+        self.text
+    }
+}
+
+pub fn init(text: &'static str) {
+    CMDLINE.init_once(|| Cmdline::parse(text.trim()));
+}
+
+// the parsed command line, or the defaults when the ramdisk has none
+pub fn cmdline() -> &'static Cmdline {
+    CMDLINE.get().unwrap_or(&DEFAULT_CMDLINE)
+}
+
+fn parse_options(text: &'static str) -> impl Iterator<Item = (&'static str, &'static str)> {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .map(|option| option.split_once('=').unwrap_or((option, "")))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "" | "1" | "on" | "yes" | "true" => Some(true),
+        "0" | "off" | "no" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_size(value: &str) -> Option<usize> {
+    let (number, multiplier) = match value.as_bytes().last()? {
+        b'K' | b'k' => (&value[..value.len() - 1], 1024),
+        b'M' | b'm' => (&value[..value.len() - 1], 1024 * 1024),
+        b'G' | b'g' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let mut components = value.split(',').map(|component| component.trim().parse::<u8>());
+    let color = [components.next()?.ok()?, components.next()?.ok()?, components.next()?.ok()?];
+    match components.next() {
+        Some(_) => None,
+        None => Some(color),
+    }
+}
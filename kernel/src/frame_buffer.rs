@@ -2,13 +2,16 @@ use bootloader_api::info::{ FrameBufferInfo, PixelFormat };
 use conquer_once::spin::OnceCell;
 use core::{ fmt, ptr };
 use spinning_top::Spinlock;
+use crate::cmdline;
 
+// path of the console font inside the ramdisk
+pub const FONT_PATH: &str = "fonts/Uni2-Fixed16.psf";
 // supoort only psf1 currently
 // refer to https://en.wikipedia.org/wiki/PC_Screen_Font
-const FONT: &'static [u8] = include_bytes!("fonts/Uni2-Fixed16.psf");
-const HEADER_SIZE: usize = FONT[1] as usize;
-const CHAR_SIZE: usize = FONT[3] as usize;
-const CHAR_HEIGHT: usize = CHAR_SIZE;
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TABLE: u8 = 0x02;
 const CHAR_WIDTH: usize = 8 as usize; // in psf 1 the width is always 8
 
 const LINE_SPACING: usize = 5;
@@ -16,8 +19,9 @@ const LETTER_SPACING: usize = 1;
 const SCREEN_PADDING: usize = 5;
 const BACKUP_CHAR: char = '?';
 
-// const IMG: &'static [u8] = include_bytes!("./images/forest.bmp");
+// const IMG_PATH: &str = "images/forest.bmp";
 
+#[derive(Clone, Copy)]
 pub struct Color {
     r: u8,
     g: u8,
@@ -29,24 +33,51 @@ pub struct Color {
 // r: 73, g: 136, b: 221, a: 0 // bright blue nice!
 const COLOR: Color = Color { r: 243, g: 98, b: 95, a: 0 };
 
+pub struct Font {
+    chars_data: &'static [u8],
+    unicode_table: &'static [u8],
+    // bytes per glyph, which is also the glyph height since every row is one byte
+    char_size: usize,
+}
+
+impl Font {
+    pub fn parse_psf1(data: &'static [u8]) -> Option<Font> {
+        if data.len() < PSF1_HEADER_SIZE || data[..2] != PSF1_MAGIC {
+            return None;
+        }
+        let mode = data[2];
+        let char_size = data[3] as usize;
+        if mode & PSF1_MODE_HAS_TABLE == 0 {
+            return None;
+        }
+        let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+        let table_start = PSF1_HEADER_SIZE + glyph_count * char_size;
+        Some(Font {
+            chars_data: data.get(PSF1_HEADER_SIZE..table_start)?,
+            unicode_table: data.get(table_start..)?,
+            char_size,
+        })
+    }
+}
+
 pub struct FrameBufferWriter {
     framebuffer: &'static mut [u8],
     info: FrameBufferInfo,
     x_pos: usize,
     y_pos: usize,
-    chars_data: &'static [u8],
-    unicode_table: &'static [u8],
+    font: Font,
+    color: Color,
 }
 
 impl FrameBufferWriter {
-    pub fn new(framebuffer: &'static mut [u8], info: FrameBufferInfo) -> Self {
+    pub fn new(framebuffer: &'static mut [u8], info: FrameBufferInfo, font: Font, color: Color) -> Self {
         let mut frame_buffer_writer = Self {
             framebuffer,
             info,
             x_pos: 0,
             y_pos: 0,
-            chars_data: &FONT[HEADER_SIZE..512 * CHAR_SIZE],
-            unicode_table: &FONT[HEADER_SIZE + 512 * CHAR_SIZE..],
+            font,
+            color,
         };
         frame_buffer_writer.clear();
         frame_buffer_writer
@@ -58,11 +89,15 @@ impl FrameBufferWriter {
     fn height(&self) -> usize {
         self.info.height
     }
+
+    fn char_height(&self) -> usize {
+        self.font.char_size
+    }
     fn newline(&mut self) {
-        if self.y_pos + CHAR_HEIGHT + SCREEN_PADDING > self.height() {
+        if self.y_pos + self.char_height() + SCREEN_PADDING > self.height() {
             self.shift();
         } else {
-            self.y_pos += CHAR_HEIGHT + LINE_SPACING;
+            self.y_pos += self.char_height() + LINE_SPACING;
         }
         self.carriage_return()
     }
@@ -86,21 +121,21 @@ impl FrameBufferWriter {
         // 0xFFFF
         // all those unicode chars point to the same glyph in the font data
         // which could be for example the char 'A' depending on the font.
-        for i in 0..self.unicode_table.len() / 2 {
-            let value = u16::from_le_bytes([self.unicode_table[i * 2], self.unicode_table[i * 2 + 1]]);
+        for i in 0..self.font.unicode_table.len() / 2 {
+            let value = u16::from_le_bytes([self.font.unicode_table[i * 2], self.font.unicode_table[i * 2 + 1]]);
             if value == 0xffff {
                 code_index += 1;
             } else if value == (char as u16) {
-                return Some(code_index * CHAR_SIZE);
+                return Some(code_index * self.font.char_size);
             }
         }
         return None;
     }
     fn get_glyph_data(&mut self, char_code: char) -> Option<&'static [u8]> {
         let char_start = self.get_char_position(char_code)?;
-        let char_end = char_start + CHAR_SIZE;
-        if char_end <= self.chars_data.len() {
-            Some(&self.chars_data[char_start..char_end])
+        let char_end = char_start + self.font.char_size;
+        if char_end <= self.font.chars_data.len() {
+            Some(&self.font.chars_data[char_start..char_end])
         } else {
             None
         }
@@ -125,12 +160,12 @@ impl FrameBufferWriter {
     }
     fn render_char(&mut self, char: char) {
         let glyph = self.get_glyph_data(char).unwrap_or_else(|| self.get_glyph_data(BACKUP_CHAR).unwrap());
-        for row in 0..CHAR_HEIGHT {
+        for row in 0..self.char_height() {
             for col in 0..CHAR_WIDTH {
                 let index = row * CHAR_WIDTH + col;
                 let bit = glyph[index / 8] & (1 << (7 - (index % 8)));
                 if bit != 0 {
-                    self.write_pixel(self.x_pos + col, self.y_pos + row, COLOR);
+                    self.write_pixel(self.x_pos + col, self.y_pos + row, self.color);
                 }
             }
         }
@@ -138,7 +173,7 @@ impl FrameBufferWriter {
         self.x_pos += 8 + LETTER_SPACING;
     }
     fn shift(&mut self) {
-        let line_size = self.width() * (CHAR_HEIGHT + LINE_SPACING) * self.info.bytes_per_pixel;
+        let line_size = self.width() * (self.char_height() + LINE_SPACING) * self.info.bytes_per_pixel;
         let buffer_length = self.framebuffer.len();
         let copy_size = buffer_length - line_size;
         unsafe {
@@ -147,7 +182,7 @@ impl FrameBufferWriter {
             core::ptr::copy(src_pts, dest_pts, copy_size);
         }
         self.framebuffer[buffer_length - line_size..].fill(0);
-        self.y_pos -= CHAR_HEIGHT + LINE_SPACING;
+        self.y_pos -= self.char_height() + LINE_SPACING;
     }
     fn write_char(&mut self, char: char) {
         match char {
@@ -155,7 +190,7 @@ impl FrameBufferWriter {
             '\r' => self.carriage_return(),
             char => {
                 let new_xpos = self.x_pos + CHAR_WIDTH;
-                let new_ypos = self.y_pos + CHAR_HEIGHT + LINE_SPACING;
+                let new_ypos = self.y_pos + self.char_height() + LINE_SPACING;
                 if new_xpos >= self.width() {
                     self.newline();
                 }
@@ -169,7 +204,7 @@ impl FrameBufferWriter {
     // fn image(&mut self) {
     //     let bpm_pixeldata_offset: usize = 54;
     //     let bytes_per_pixel: usize = 3;
-    //     let pixel_data = &IMG[bpm_pixeldata_offset..];
+    //     let pixel_data = &crate::ramdisk::file(IMG_PATH).unwrap()[bpm_pixeldata_offset..];
     //     let width = 1000;
     //     let height = 667;
 
@@ -198,8 +233,12 @@ impl fmt::Write for FrameBufferWriter {
 }
 pub static WRITER: OnceCell<Spinlock<FrameBufferWriter>> = OnceCell::uninit();
 
-pub fn init(framebuffer: &'static mut [u8], info: FrameBufferInfo) {
-    WRITER.get_or_init(move || Spinlock::new(FrameBufferWriter::new(framebuffer, info)));
+pub fn init(framebuffer: &'static mut [u8], info: FrameBufferInfo, font: Font) {
+    let color = match cmdline::cmdline().console_color {
+        Some([r, g, b]) => Color { r, g, b, a: 0 },
+        None => COLOR,
+    };
+    WRITER.get_or_init(move || Spinlock::new(FrameBufferWriter::new(framebuffer, info, font, color)));
 }
 
 #[macro_export]
@@ -219,7 +258,12 @@ pub fn _print(args: fmt::Arguments) {
     use x86_64::instructions::interrupts;
     // FIX ME find another way to handle this
     interrupts::without_interrupts(|| {
-        WRITER.try_get().unwrap().lock().write_fmt(args).unwrap();
+        // everything printed also goes to the serial port so that it can be captured from the host
+        crate::serial::SERIAL1.lock().write_fmt(args).unwrap();
+        // the frame buffer is not available in the early boot stages
+        if let Ok(writer) = WRITER.try_get() {
+            writer.lock().write_fmt(args).unwrap();
+        }
     });
 }
 
@@ -1,4 +1,5 @@
 // Global Descriptn Table
+use alloc::{ boxed::Box, vec };
 use lazy_static::lazy_static;
 use x86_64::VirtAddr;
 use x86_64::structures::tss::TaskStateSegment;
@@ -19,41 +20,62 @@ struct Gdt {
     selector: Selectors,
 }
 
+// stack of the double fault handler, so that a kernel stack overflow does not triple fault
+const DOUBLE_FAULT_STACK_SIZE: usize = 4096 * 5;
+
 lazy_static! {
     static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
-        tss
+        static mut STACK: [u8; DOUBLE_FAULT_STACK_SIZE] = [0; DOUBLE_FAULT_STACK_SIZE];
+        let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
+        new_tss(stack_start + DOUBLE_FAULT_STACK_SIZE)
     };
 }
 
 lazy_static! {
-    static ref GDT: Gdt = {
+    static ref GDT: Gdt = Gdt::new(&TSS);
+}
+
+impl Gdt {
+    fn new(tss: &'static TaskStateSegment) -> Gdt {
         let mut gdt = GlobalDescriptorTable::new();
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
         let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(tss));
         Gdt { gdt, selector: Selectors { code_selector, data_selector, tss_selector } }
-    };
+    }
+
+    fn load(&'static self) {
+        self.gdt.load();
+        unsafe {
+            CS::set_reg(self.selector.code_selector);
+
+            DS::set_reg(self.selector.data_selector);
+            ES::set_reg(self.selector.data_selector);
+            GS::set_reg(self.selector.data_selector);
+            FS::set_reg(self.selector.data_selector);
+            SS::set_reg(self.selector.data_selector);
+
+            load_tss(self.selector.tss_selector);
+        }
+    }
+}
+
+fn new_tss(double_fault_stack_end: VirtAddr) -> TaskStateSegment {
+    let mut tss = TaskStateSegment::new();
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX] = double_fault_stack_end;
+    tss
 }
 
 pub fn init() {
-    GDT.gdt.load();
-    unsafe {
-        CS::set_reg(GDT.selector.code_selector);
-
-        DS::set_reg(GDT.selector.data_selector);
-        ES::set_reg(GDT.selector.data_selector);
-        GS::set_reg(GDT.selector.data_selector);
-        FS::set_reg(GDT.selector.data_selector);
-        SS::set_reg(GDT.selector.data_selector);
-        
-        load_tss(GDT.selector.tss_selector);
-    }
-}
\ No newline at end of file
+    GDT.load();
+}
+
+// Every application processor needs a TSS of its own, the CPU marks a loaded TSS as busy.
+// They live as long as the CPU runs, so they are leaked.
+pub fn init_ap() {
+    let stack = Box::leak(vec![0u8; DOUBLE_FAULT_STACK_SIZE].into_boxed_slice());
+    let stack_start = VirtAddr::from_ptr(stack.as_ptr());
+    let tss = Box::leak(Box::new(new_tss(stack_start + DOUBLE_FAULT_STACK_SIZE)));
+    let gdt = Box::leak(Box::new(Gdt::new(tss)));
+    gdt.load();
+}
@@ -6,6 +6,10 @@ pub extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFram
     println!("EXCEPTION: BREAKPOINT: {stack_frame:#?}");
 }
 pub extern "x86-interrupt" fn non_maskable_interrupt_handler(stack_frame: InterruptStackFrame) {
+    // another CPU panicked and stops the rest
+    if super::ipi::is_stopping() {
+        hlt_loop();
+    }
     panic!("EXCEPTION: NON MASKABLE:  {stack_frame:#?}");
 }
 pub extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
@@ -1,18 +1,20 @@
 use x86_64::{ structures::idt::InterruptStackFrame, instructions::port::Port };
 use crate::task::{ keyboard, mouse };
+use crate::{ memory, time, smp };
 
-use super::end_of_interrupt;
+use super::{ end_of_interrupt, local_apic };
 
 pub extern "x86-interrupt" fn apic_error_handler(_stack_frame: InterruptStackFrame) {
     unsafe {
-        let lapic = super::LOCAL_APIC.get().expect("Failed to get Local APIC in apic error handler").lock();
+        let lapic = smp::current().local_apic().lock();
         let flags = lapic.error_flags();
         panic!("EXCEPTION: APIC ERROR: {:#?}", flags);
     }
 }
 
 pub extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    // TODO handle the timer interrupt
+    time::tick();
+    local_apic::rearm_timer();
     end_of_interrupt();
 }
 pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
@@ -27,4 +29,12 @@ pub extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptSta
     let packet: u8 = unsafe { port.read() };
     mouse::add_packet(packet);
     end_of_interrupt();
-}
\ No newline at end of file
+}
+pub extern "x86-interrupt" fn tlb_shootdown_handler(_stack_frame: InterruptStackFrame) {
+    memory::handle_tlb_shootdown();
+    end_of_interrupt();
+}
+// only interrupts a halted CPU, the wakeup itself is the work
+pub extern "x86-interrupt" fn wakeup_handler(_stack_frame: InterruptStackFrame) {
+    end_of_interrupt();
+}
@@ -1,31 +1,137 @@
+use alloc::vec::Vec;
+use acpi::platform::interrupt::{ InterruptSourceOverride, Polarity, TriggerMode };
+use log::{ info, warn };
+use spinning_top::Spinlock;
 use x2apic::ioapic::{ IoApic, RedirectionTableEntry, IrqFlags };
 use crate::memory;
-use super::InterruptIndex;
 
 pub const IO_APIC_OFFSET: u8 = 100;
+// the legacy PICs had 16 inputs, ISA IRQs are numbered like them
+const ISA_IRQ_COUNT: usize = 16;
+
+// every I/O APIC with the global system interrupts (GSIs) wired to its inputs
+static IO_APICS: Spinlock<Vec<(GsiRange, IoApic)>> = Spinlock::new(Vec::new());
+static ISA_OVERRIDES: Spinlock<[Option<IsaOverride>; ISA_IRQ_COUNT]> = Spinlock::new([None; ISA_IRQ_COUNT]);
 
 #[repr(u8)]
-pub enum IoApicTableIndex {
+pub enum IsaIrq {
     Keyboard = 1,
     Mouse = 12,
 }
 
-pub unsafe fn init_io_apic(io_apic_address: u64, local_apic_id: u8) {
+// An ISA IRQ that the MADT wires to a different GSI or with a different polarity or trigger mode
+// than ISA devices use, which is edge triggered and active high on the same GSI.
+#[derive(Debug, Clone, Copy)]
+struct IsaOverride {
+    gsi: u32,
+    // `None` keeps the default of the device
+    level_triggered: Option<bool>,
+    active_low: Option<bool>,
+}
+
+// The inputs of an I/O APIC are numbered from the GSI base the MADT gives for it, up to the
+// highest entry of its redirection table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GsiRange {
+    pub gsi_base: u32,
+    pub max_entry: u8,
+}
+
+impl GsiRange {
+    // the redirection table entry of `gsi`, `None` if it belongs to another I/O APIC
+    pub fn entry(&self, gsi: u32) -> Option<u8> {
+        let entry = gsi.checked_sub(self.gsi_base)?;
+        if entry > self.max_entry as u32 {
+            return None;
+        }
+        Some(entry as u8)
+    }
+}
+
+pub unsafe fn init_io_apic(io_apic_address: u64, gsi_base: u32) {
     memory::identity_map(io_apic_address, None);
 
     let mut io_apic = IoApic::new(io_apic_address);
     io_apic.init(IO_APIC_OFFSET);
+    let gsi_range = GsiRange { gsi_base, max_entry: io_apic.max_table_entry() };
+    IO_APICS.lock().push((gsi_range, io_apic));
+}
 
-    register_io_apic_entry(&mut io_apic, local_apic_id, InterruptIndex::Keyboard as u8, IoApicTableIndex::Keyboard as u8);
-    register_io_apic_entry(&mut io_apic, local_apic_id, InterruptIndex::Mouse as u8, IoApicTableIndex::Mouse as u8);
+pub fn init_isa_overrides(overrides: &[InterruptSourceOverride]) {
+    let mut isa_overrides = ISA_OVERRIDES.lock();
+    for interrupt_override in overrides {
+        let slot = match isa_overrides.get_mut(interrupt_override.isa_source as usize) {
+            Some(slot) => slot,
+            None => {
+                warn!("Ignoring interrupt source override for invalid ISA IRQ {}", interrupt_override.isa_source);
+                continue;
+            }
+        };
+        let isa_override = IsaOverride {
+            gsi: interrupt_override.global_system_interrupt,
+            level_triggered: match interrupt_override.trigger_mode {
+                TriggerMode::SameAsBus => None,
+                TriggerMode::Edge => Some(false),
+                TriggerMode::Level => Some(true),
+            },
+            active_low: match interrupt_override.polarity {
+                Polarity::SameAsBus => None,
+                Polarity::ActiveHigh => Some(false),
+                Polarity::ActiveLow => Some(true),
+            },
+        };
+        info!("ISA IRQ {} is overridden: {:?}", interrupt_override.isa_source, isa_override);
+        *slot = Some(isa_override);
+    }
 }
 
-unsafe fn register_io_apic_entry(io_apic: &mut IoApic, lapic_id: u8, int_index: u8, irq_index: u8) {
+// the GSI ranges of all I/O APICs in MADT order
+pub fn gsi_ranges() -> Vec<GsiRange> {
+    IO_APICS.lock().iter().map(|(gsi_range, _)| *gsi_range).collect()
+}
+
+// the GSI and flags of an ISA IRQ, `default_flags` apply unless the MADT overrides them
+pub fn isa_irq_to_gsi(irq: u8, default_flags: IrqFlags) -> (u32, IrqFlags) {
+    let isa_override = match ISA_OVERRIDES.lock().get(irq as usize).copied().flatten() {
+        Some(isa_override) => isa_override,
+        None => {
+            return (irq as u32, default_flags);
+        }
+    };
+    let mut flags = default_flags;
+    if let Some(level_triggered) = isa_override.level_triggered {
+        flags.set(IrqFlags::LEVEL_TRIGGERED, level_triggered);
+    }
+    if let Some(active_low) = isa_override.active_low {
+        flags.set(IrqFlags::LOW_ACTIVE, active_low);
+    }
+    (isa_override.gsi, flags)
+}
+
+// deliver a GSI to `vector` on the I/O APIC whose inputs include it, returns false if there is none
+pub unsafe fn route_gsi(gsi: u32, vector: u8, flags: IrqFlags, local_apic_id: u8) -> bool {
+    let mut io_apics = IO_APICS.lock();
+    let (index, io_apic) = match io_apics.iter_mut().find_map(|(gsi_range, io_apic)| Some((gsi_range.entry(gsi)?, io_apic))) {
+        Some(input) => input,
+        None => {
+            return false;
+        }
+    };
     let mut entry = RedirectionTableEntry::default();
     entry.set_mode(x2apic::ioapic::IrqMode::Fixed);
-    entry.set_dest(lapic_id);
-    entry.set_vector(int_index);
-    entry.set_flags(IrqFlags::LEVEL_TRIGGERED | IrqFlags::LOW_ACTIVE | IrqFlags::MASKED);
-    io_apic.set_table_entry(irq_index, entry);
-    io_apic.enable_irq(irq_index);
-}
\ No newline at end of file
+    entry.set_dest(local_apic_id);
+    entry.set_vector(vector);
+    entry.set_flags(flags);
+    io_apic.set_table_entry(index, entry);
+    true
+}
+
+// stop delivering a GSI, its redirection entry stays programmed
+pub unsafe fn mask_gsi(gsi: u32) {
+    let mut io_apics = IO_APICS.lock();
+    for (gsi_range, io_apic) in io_apics.iter_mut() {
+        if let Some(index) = gsi_range.entry(gsi) {
+            io_apic.disable_irq(index);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use core::sync::atomic::{ AtomicBool, Ordering };
+use x2apic::lapic::IpiAllShorthand;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::smp;
+use super::{ local_apic, InterruptIndex };
+
+// set before the other CPUs are stopped with an NMI, which is otherwise an error
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+// receivers of an inter-processor interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpiTarget {
+    // a single CPU by its APIC ID
+    Cpu(u32),
+    AllIncludingSelf,
+    AllExcludingSelf,
+}
+
+// interrupts are disabled while the local APIC is locked, the end of interrupt needs it as well
+pub fn send_ipi(target: IpiTarget, vector: InterruptIndex) {
+    without_interrupts(|| {
+        let mut local_apic = smp::current().local_apic().lock();
+        unsafe {
+            match target {
+                IpiTarget::Cpu(apic_id) => local_apic.send_ipi(vector as u8, local_apic::ipi_destination(apic_id)),
+                IpiTarget::AllIncludingSelf => local_apic.send_ipi_all(vector as u8, IpiAllShorthand::AllIncludingSelf),
+                IpiTarget::AllExcludingSelf => local_apic.send_ipi_all(vector as u8, IpiAllShorthand::AllExcludingSelf),
+            }
+        }
+    });
+}
+
+// NMIs are delivered even to CPUs that run with interrupts disabled
+pub fn send_nmi(target: IpiTarget) {
+    without_interrupts(|| {
+        let mut local_apic = smp::current().local_apic().lock();
+        unsafe {
+            match target {
+                IpiTarget::Cpu(apic_id) => local_apic.send_nmi(local_apic::ipi_destination(apic_id)),
+                IpiTarget::AllIncludingSelf => local_apic.send_nmi_all(IpiAllShorthand::AllIncludingSelf),
+                IpiTarget::AllExcludingSelf => local_apic.send_nmi_all(IpiAllShorthand::AllExcludingSelf),
+            }
+        }
+    });
+}
+
+// Start an application processor: an INIT IPI resets it into the wait-for-SIPI state, a startup
+// IPI then lets it execute real mode code at physical page `page`.
+pub fn send_init_ipi(apic_id: u32) {
+    let destination = local_apic::ipi_destination(apic_id);
+    without_interrupts(|| unsafe { smp::current().local_apic().lock().send_init_ipi(destination) });
+}
+pub fn send_startup_ipi(apic_id: u32, page: u8) {
+    let destination = local_apic::ipi_destination(apic_id);
+    without_interrupts(|| unsafe { smp::current().local_apic().lock().send_sipi(page, destination) });
+}
+
+// Halt every other CPU, e.g. on a panic. The local APIC may be locked by the CPU that panicked,
+// in that case the others keep running.
+pub fn stop_other_cpus() {
+    STOPPING.store(true, Ordering::SeqCst);
+    let local_apic = smp::try_current().and_then(|per_cpu| per_cpu.try_local_apic());
+    let mut local_apic = match local_apic.and_then(|local_apic| local_apic.try_lock()) {
+        Some(local_apic) => local_apic,
+        None => {
+            return;
+        }
+    };
+    unsafe { local_apic.send_nmi_all(IpiAllShorthand::AllExcludingSelf) }
+}
+
+// whether an NMI was sent by `stop_other_cpus`
+pub fn is_stopping() -> bool {
+    STOPPING.load(Ordering::SeqCst)
+}
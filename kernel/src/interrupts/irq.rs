@@ -0,0 +1,129 @@
+use alloc::{ boxed::Box, collections::BTreeMap, vec::Vec };
+use core::sync::atomic::{ AtomicU64, Ordering };
+use spinning_top::Spinlock;
+use log::warn;
+use x2apic::ioapic::IrqFlags;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::idt::{ HandlerFunc, InterruptStackFrame };
+
+use super::{ io_apic, end_of_interrupt };
+
+// vectors handed out by `register_irq`, the ones below are used by the fixed interrupts
+pub const FIRST_DYNAMIC_VECTOR: u8 = 0x30;
+pub const DYNAMIC_VECTOR_COUNT: usize = 32;
+
+// Returns whether the interrupt came from the handler's device, devices on a shared line are
+// asked one after the other. Handlers run with the line locked and must not register IRQs.
+type IrqHandler = Box<dyn Fn() -> bool + Send + Sync>;
+
+struct IrqLine {
+    gsi: u32,
+    flags: IrqFlags,
+    handlers: Vec<(u64, IrqHandler)>,
+}
+
+// the handlers of every dynamic vector, `None` if the vector is free
+static LINES: [Spinlock<Option<IrqLine>>; DYNAMIC_VECTOR_COUNT] = [const { Spinlock::new(None) }; DYNAMIC_VECTOR_COUNT];
+// GSI to dynamic vector, lines are shared by registering the same GSI again
+static GSI_VECTORS: Spinlock<BTreeMap<u32, u8>> = Spinlock::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    NoFreeVector,
+    NoIoApic(u32),
+    // the GSI is already registered with another trigger mode or polarity
+    FlagsMismatch(u32),
+}
+
+// identifies a registered handler, to remove it again
+#[derive(Debug, PartialEq, Eq)]
+pub struct IrqHandle {
+    pub gsi: u32,
+    pub vector: u8,
+    id: u64,
+}
+
+// Call `handler` whenever the global system interrupt `gsi` fires. The first handler of a GSI
+// allocates a vector and programs the I/O APIC, later ones share the line.
+pub fn register_irq(gsi: u32, flags: IrqFlags, handler: impl Fn() -> bool + Send + Sync + 'static) -> Result<IrqHandle, IrqError> {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let handler: IrqHandler = Box::new(handler);
+    without_interrupts(|| {
+        let mut gsi_vectors = GSI_VECTORS.lock();
+        if let Some(&vector) = gsi_vectors.get(&gsi) {
+            let mut line = LINES[(vector - FIRST_DYNAMIC_VECTOR) as usize].lock();
+            let line = line.as_mut().expect("Registered GSI without a line");
+            if line.flags != flags {
+                return Err(IrqError::FlagsMismatch(gsi));
+            }
+            line.handlers.push((id, handler));
+            return Ok(IrqHandle { gsi, vector, id });
+        }
+
+        let index = LINES.iter().position(|line| line.lock().is_none()).ok_or(IrqError::NoFreeVector)?;
+        let vector = FIRST_DYNAMIC_VECTOR + (index as u8);
+        *LINES[index].lock() = Some(IrqLine { gsi, flags, handlers: alloc::vec![(id, handler)] });
+        if !super::route_gsi(gsi, vector, flags) {
+            LINES[index].lock().take();
+            return Err(IrqError::NoIoApic(gsi));
+        }
+        gsi_vectors.insert(gsi, vector);
+        Ok(IrqHandle { gsi, vector, id })
+    })
+}
+
+// register an ISA IRQ on the GSI the MADT maps it to, `default_flags` apply unless overridden
+pub fn register_isa_irq(irq: u8, default_flags: IrqFlags, handler: impl Fn() -> bool + Send + Sync + 'static) -> Result<IrqHandle, IrqError> {
+    let (gsi, flags) = io_apic::isa_irq_to_gsi(irq, default_flags);
+    register_irq(gsi, flags, handler)
+}
+
+// remove a handler, the GSI is masked and its vector freed once the last handler is gone
+pub fn unregister_irq(handle: IrqHandle) {
+    without_interrupts(|| {
+        let mut gsi_vectors = GSI_VECTORS.lock();
+        let mut line = LINES[(handle.vector - FIRST_DYNAMIC_VECTOR) as usize].lock();
+        let handlers = match line.as_mut() {
+            Some(line) => &mut line.handlers,
+            None => {
+                return;
+            }
+        };
+        handlers.retain(|(id, _)| *id != handle.id);
+        if handlers.is_empty() {
+            unsafe { io_apic::mask_gsi(handle.gsi) };
+            gsi_vectors.remove(&handle.gsi);
+            line.take();
+        }
+    });
+}
+
+fn dispatch(index: usize) {
+    let line = LINES[index].lock();
+    if let Some(line) = line.as_ref() {
+        // every handler runs, more than one device may be asserting a level triggered line
+        let handled = line.handlers.iter().fold(false, |handled, (_, handler)| handler() | handled);
+        if !handled {
+            warn!("Unhandled interrupt on GSI {}", line.gsi);
+        }
+    }
+    drop(line);
+    end_of_interrupt();
+}
+
+extern "x86-interrupt" fn dispatcher<const INDEX: usize>(_stack_frame: InterruptStackFrame) {
+    dispatch(INDEX);
+}
+
+macro_rules! dispatchers {
+    ($($index:literal),*) => {
+        [$(dispatcher::<$index> as HandlerFunc),*]
+    };
+}
+
+// installed in the IDT from `FIRST_DYNAMIC_VECTOR` on
+pub static DISPATCHERS: [HandlerFunc; DYNAMIC_VECTOR_COUNT] = dispatchers!(
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+    16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31
+);
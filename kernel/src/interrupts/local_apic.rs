@@ -1,16 +1,75 @@
+use core::sync::atomic::{ fence, AtomicU64, Ordering };
+use core::time::Duration;
+use log::{ info, warn };
 use x2apic::lapic::{ LocalApicBuilder, TimerDivide, LocalApic, TimerMode };
+use x86_64::registers::model_specific::Msr;
 
-use crate::memory;
+use crate::{ memory, time, cmdline };
+use crate::time::{ pit, hpet, tsc };
 use super::InterruptIndex;
 
+const TIMER_DIVIDE: u64 = 16;
+const CALIBRATION_PERIOD: Duration = Duration::from_millis(10);
+// used when the timer can not be calibrated, this is the bus clock QEMU emulates
+const ASSUMED_BUS_FREQUENCY_HZ: u64 = 1_000_000_000;
+const IA32_TSC_DEADLINE: u32 = 0x6e0;
+const IA32_APIC_BASE: u32 = 0x1b;
+const X2APIC_ENABLE: u64 = 1 << 10;
+
+// every CPU sees its own local APIC at this address
+static LOCAL_APIC_ADDRESS: AtomicU64 = AtomicU64::new(0);
+
+// ticks per second of the timer, after the divider in periodic mode or the TSC in TSC-deadline mode
+static TIMER_FREQUENCY_HZ: AtomicU64 = AtomicU64::new(0);
+// TSC cycles between two timer interrupts, zero in periodic mode
+static TSC_DEADLINE_INTERVAL: AtomicU64 = AtomicU64::new(0);
+static NEXT_TSC_DEADLINE: AtomicU64 = AtomicU64::new(0);
+
 pub fn init_local_apic(local_apic_address: u64) -> LocalApic {
     memory::identity_map(local_apic_address, None);
+    LOCAL_APIC_ADDRESS.store(local_apic_address, Ordering::Relaxed);
+    // counts down from the maximum while calibrating, it is reprogrammed afterwards
+    let mut local_apic = build_local_apic(local_apic_address, u32::MAX);
+
+    let timer_hz = cmdline::cmdline().timer_hz as u64;
+    let tsc_deadline = cmdline::cmdline().tsc_deadline && tsc::deadline_supported();
+    let timer_frequency = match tsc_deadline {
+        true => None,
+        false => calibrate_timer(&mut local_apic),
+    };
+    match timer_frequency {
+        Some(frequency) => {
+            info!("Local APIC timer calibrated: {frequency} Hz");
+            start_periodic_timer(&mut local_apic, frequency, timer_hz);
+        }
+        None => match tsc::measure_frequency().filter(|_| tsc::deadline_supported()) {
+            Some(frequency) => {
+                info!("Local APIC timer in TSC-deadline mode, TSC frequency: {frequency} Hz");
+                start_tsc_deadline_timer(&mut local_apic, frequency, timer_hz);
+            }
+            None => {
+                warn!("Failed to calibrate the Local APIC timer, assuming a {ASSUMED_BUS_FREQUENCY_HZ} Hz bus clock");
+                start_periodic_timer(&mut local_apic, ASSUMED_BUS_FREQUENCY_HZ / TIMER_DIVIDE, timer_hz);
+            }
+        },
+    }
+    local_apic
+}
+
+// The local APIC of an application processor, at the same address as the one of the boot
+// processor. Its timer stays stopped, the boot processor alone keeps time.
+pub fn init_ap_local_apic() -> LocalApic {
+    build_local_apic(LOCAL_APIC_ADDRESS.load(Ordering::Relaxed), 0)
+}
+
+fn build_local_apic(local_apic_address: u64, timer_initial: u32) -> LocalApic {
     let mut local_apic = LocalApicBuilder::new()
         //https://wiki.osdev.org/APIC_timer
         .timer_vector(InterruptIndex::Timer as usize)
         // timer divide controlls how fast the timer interrupt is
-        .timer_divide(TimerDivide::Div64)
-        .timer_mode(TimerMode::Periodic)
+        .timer_divide(TimerDivide::Div16)
+        .timer_mode(TimerMode::OneShot)
+        .timer_initial(timer_initial)
         .error_vector(InterruptIndex::ApicError as usize)
         // mask the spurious vector
         .spurious_vector(0xff)
@@ -21,4 +80,84 @@ pub fn init_local_apic(local_apic_address: u64) -> LocalApic {
         local_apic.enable();
     }
     local_apic
-}
\ No newline at end of file
+}
+
+// The APIC ID as the MADT lists it. In xAPIC mode the ID register and the destination field of
+// the ICR keep it in their top byte, in x2APIC mode they hold all 32 bits.
+pub fn apic_id(local_apic: &LocalApic) -> u32 {
+    let id = unsafe { local_apic.id() };
+    match is_x2apic() {
+        true => id,
+        false => id >> 24,
+    }
+}
+pub fn ipi_destination(apic_id: u32) -> u32 {
+    match is_x2apic() {
+        true => apic_id,
+        false => apic_id << 24,
+    }
+}
+
+fn is_x2apic() -> bool {
+    unsafe { Msr::new(IA32_APIC_BASE).read() & X2APIC_ENABLE != 0 }
+}
+
+// timer ticks per second as measured at boot
+pub fn timer_frequency() -> u64 {
+    TIMER_FREQUENCY_HZ.load(Ordering::Relaxed)
+}
+
+// called from the timer interrupt handler, the TSC-deadline timer fires only once per deadline
+pub fn rearm_timer() {
+    let interval = TSC_DEADLINE_INTERVAL.load(Ordering::Relaxed);
+    if interval == 0 {
+        return;
+    }
+    // keep the deadlines on a fixed grid so that late interrupts do not add up to drift,
+    // unless we fell so far behind that the next deadline has already passed
+    let now = tsc::read();
+    let mut deadline = NEXT_TSC_DEADLINE.load(Ordering::Relaxed) + interval;
+    if deadline <= now {
+        deadline = now + interval;
+    }
+    NEXT_TSC_DEADLINE.store(deadline, Ordering::Relaxed);
+    unsafe { Msr::new(IA32_TSC_DEADLINE).write(deadline) }
+}
+
+// count down the timer for a known time on the HPET or the PIT, returns the timer frequency after the divider
+fn calibrate_timer(local_apic: &mut LocalApic) -> Option<u64> {
+    let start = || unsafe { local_apic.set_timer_initial(u32::MAX) };
+    let started = match hpet::is_available() {
+        true => hpet::measure(CALIBRATION_PERIOD, start),
+        false => pit::measure(CALIBRATION_PERIOD, start),
+    };
+    let elapsed = u32::MAX - unsafe { local_apic.timer_current() };
+    if !started || elapsed == 0 {
+        return None;
+    }
+    Some((elapsed as u64) * 1_000_000_000 / (CALIBRATION_PERIOD.as_nanos() as u64))
+}
+
+fn start_periodic_timer(local_apic: &mut LocalApic, frequency: u64, timer_hz: u64) {
+    let initial_count = (frequency / timer_hz).clamp(1, u32::MAX as u64);
+    TIMER_FREQUENCY_HZ.store(frequency, Ordering::Relaxed);
+    time::set_tick_period(Duration::from_nanos(initial_count * 1_000_000_000 / frequency));
+    unsafe {
+        local_apic.set_timer_mode(TimerMode::Periodic);
+        local_apic.set_timer_initial(initial_count as u32);
+    }
+}
+
+fn start_tsc_deadline_timer(local_apic: &mut LocalApic, frequency: u64, timer_hz: u64) {
+    let interval = (frequency / timer_hz).max(1);
+    TIMER_FREQUENCY_HZ.store(frequency, Ordering::Relaxed);
+    TSC_DEADLINE_INTERVAL.store(interval, Ordering::Relaxed);
+    time::set_tick_period(Duration::from_nanos(interval * 1_000_000_000 / frequency));
+    unsafe {
+        local_apic.set_timer_mode(TimerMode::TscDeadline);
+        // the mode change has to be visible before the deadline MSR is written
+        fence(Ordering::SeqCst);
+        NEXT_TSC_DEADLINE.store(tsc::read(), Ordering::Relaxed);
+    }
+    rearm_timer();
+}
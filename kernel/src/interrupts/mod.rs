@@ -1,25 +1,29 @@
-use conquer_once::spin::OnceCell;
-use spinning_top::Spinlock;
+use core::sync::atomic::Ordering;
 use lazy_static::lazy_static;
+use log::{ info, warn };
 
 use x86_64::structures::idt::InterruptDescriptorTable;
 use x86_64::instructions::port::Port;
 
 use acpi::platform::interrupt::Apic;
 
-use x2apic::lapic::LocalApic;
-
 use pic8259::ChainedPics;
-use crate::gdt;
+use crate::{ gdt, cmdline, time, smp };
 
 mod local_apic;
 mod io_apic;
 mod exception_handlers;
 mod interrupt_handlers;
+mod irq;
+mod ipi;
 
-const IRQ_INDEX: u8 = 0x20;
+pub use local_apic::timer_frequency;
+pub use irq::{ register_irq, register_isa_irq, unregister_irq, IrqHandle, IrqError, FIRST_DYNAMIC_VECTOR, DYNAMIC_VECTOR_COUNT };
+pub use io_apic::{ init_isa_overrides, isa_irq_to_gsi, gsi_ranges, GsiRange };
+pub use x2apic::ioapic::IrqFlags;
+pub use ipi::{ send_ipi, send_nmi, send_init_ipi, send_startup_ipi, stop_other_cpus, IpiTarget };
 
-pub static LOCAL_APIC: OnceCell<Spinlock<LocalApic>> = OnceCell::uninit();
+const IRQ_INDEX: u8 = 0x20;
 
 #[repr(u8)]
 pub enum InterruptIndex {
@@ -27,6 +31,9 @@ pub enum InterruptIndex {
     Keyboard = IRQ_INDEX + 1,
     Mouse = IRQ_INDEX + 12,
     ApicError = 151,
+    // inter-processor interrupts
+    TlbShootdown = 0xf0,
+    Wakeup = 0xf1,
 }
 
 lazy_static! {
@@ -51,12 +58,19 @@ lazy_static! {
         idt[InterruptIndex::Keyboard as usize].set_handler_fn(interrupt_handlers::keyboard_interrupt_handler);
         idt[InterruptIndex::Mouse as usize].set_handler_fn(interrupt_handlers::mouse_interrupt_handler);
 
+        // vectors for `register_irq`
+        for (index, dispatcher) in irq::DISPATCHERS.iter().enumerate() {
+            idt[irq::FIRST_DYNAMIC_VECTOR as usize + index].set_handler_fn(*dispatcher);
+        }
+
         idt[InterruptIndex::ApicError as usize].set_handler_fn(interrupt_handlers::apic_error_handler);
+        idt[InterruptIndex::TlbShootdown as usize].set_handler_fn(interrupt_handlers::tlb_shootdown_handler);
+        idt[InterruptIndex::Wakeup as usize].set_handler_fn(interrupt_handlers::wakeup_handler);
         idt
     };
 }
 
-pub fn init_apic(apic_info: Apic) {
+pub fn init_apic(apic_info: &Apic) {
     x86_64::instructions::interrupts::disable();
     disable_legacy_pic();
 
@@ -64,21 +78,61 @@ pub fn init_apic(apic_info: Apic) {
 
     unsafe {
         let local_apic = local_apic::init_local_apic(apic_info.local_apic_address);
-        let local_apic_id = local_apic.id();
-        println!("Initialized Local APIC: ID: {}, Version: {}", local_apic.id(), local_apic.version());
-        LOCAL_APIC.init_once(move || Spinlock::new(local_apic));
+        let apic_id = local_apic::apic_id(&local_apic);
+        info!("Initialized Local APIC: ID: {}, Version: {}", apic_id, local_apic.version());
+        smp::current().set_local_apic(local_apic, apic_id);
 
-        for io_apic in apic_info.io_apics {
-            println!("Initializing I/O APIC ID: {}", io_apic.id);
-            io_apic::init_io_apic(io_apic.address as u64, local_apic_id as u8);
+        for io_apic in &apic_info.io_apics {
+            info!("Initializing I/O APIC ID: {}", io_apic.id);
+            io_apic::init_io_apic(io_apic.address as u64, io_apic.global_system_interrupt_base);
         }
     }
-    enable_mouse();
+    io_apic::init_isa_overrides(&apic_info.interrupt_source_overrides);
+    route_isa_irq(io_apic::IsaIrq::Keyboard as u8, InterruptIndex::Keyboard as u8, IrqFlags::empty());
+    route_isa_irq(io_apic::IsaIrq::Mouse as u8, InterruptIndex::Mouse as u8, IrqFlags::empty());
+    time::hpet::init_interrupts();
+    if cmdline::cmdline().mouse {
+        enable_mouse();
+    }
     x86_64::instructions::interrupts::enable();
 }
 
+// called on each application processor, the IDT is shared by all CPUs
+pub fn init_ap() {
+    IDT.load();
+    let local_apic = local_apic::init_ap_local_apic();
+    let apic_id = local_apic::apic_id(&local_apic);
+    info!("Initialized Local APIC: ID: {}", apic_id);
+    smp::current().set_local_apic(local_apic, apic_id);
+}
+
+// the APIC ID of the CPU this runs on
+pub fn apic_id() -> u32 {
+    smp::current().apic_id()
+}
+
+// Deliver a global system interrupt to `vector` on this CPU, edge triggered and active high
+// unless `flags` say otherwise. Returns false if no I/O APIC has that input.
+pub fn route_gsi(gsi: u32, vector: u8, flags: IrqFlags) -> bool {
+    unsafe { io_apic::route_gsi(gsi, vector, flags, apic_id() as u8) }
+}
+
+// Deliver an ISA IRQ to `vector` on this CPU through the GSI the MADT maps it to. ISA devices are
+// edge triggered and active high, devices that differ like the ACPI SCI pass their own `default_flags`.
+pub fn route_isa_irq(irq: u8, vector: u8, default_flags: IrqFlags) -> bool {
+    let (gsi, flags) = io_apic::isa_irq_to_gsi(irq, default_flags);
+    let routed = route_gsi(gsi, vector, flags);
+    if !routed {
+        warn!("No I/O APIC handles ISA IRQ {irq} on GSI {gsi}");
+    }
+    routed
+}
+
 pub fn end_of_interrupt() {
-    unsafe { LOCAL_APIC.get().expect("Cannot get Local APIC").lock().end_of_interrupt() }
+    // every interrupt handler ends here
+    let per_cpu = smp::current();
+    per_cpu.stats().interrupts.fetch_add(1, Ordering::Relaxed);
+    unsafe { per_cpu.local_apic().lock().end_of_interrupt() }
 }
 
 fn disable_legacy_pic() {
@@ -0,0 +1,118 @@
+#![no_std]
+#![cfg_attr(test, no_main)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+#![feature(alloc_error_handler)]
+#![feature(const_trait_impl)]
+#![feature(const_slice_index)]
+#![feature(abi_x86_interrupt)]
+
+use bootloader_api::{ BootInfo, BootloaderConfig, config::Mapping };
+use log::{ info, warn };
+extern crate alloc;
+
+#[macro_use]
+pub mod frame_buffer;
+#[macro_use]
+pub mod serial;
+pub mod logger;
+pub mod ramdisk;
+pub mod cmdline;
+pub mod interrupts;
+pub mod gdt;
+pub mod memory;
+pub mod allocator;
+pub mod acpi;
+pub mod task;
+pub mod testing;
+pub mod time;
+pub mod smp;
+
+pub static BOOTLOADER_CONFIG: BootloaderConfig = {
+    let mut config = BootloaderConfig::new_default();
+    config.mappings.physical_memory = Some(Mapping::Dynamic);
+    config
+};
+
+// bring up every kernel subsystem, shared by the kernel binary and the test binaries
+pub fn init(boot_info: &'static mut BootInfo) {
+    let rsdp_addr = boot_info.rsdp_addr.into_option().expect("Failed to get RSDP address");
+    let physical_memory_offset = boot_info.physical_memory_offset.into_option().expect("Failed to get Physical Memory Offset");
+    let memory_regions = &boot_info.memory_regions;
+    let framebuffer_info = boot_info.framebuffer.as_ref().unwrap().info();
+    let framebuffer = boot_info.framebuffer.as_mut().unwrap().buffer_mut();
+
+    logger::init();
+
+    if ramdisk::init(boot_info.ramdisk_addr.into_option(), boot_info.ramdisk_len) {
+        info!("Ramdisk initialized: {} bytes.", boot_info.ramdisk_len);
+    } else {
+        warn!("No ramdisk was loaded by the bootloader.");
+    }
+
+    let cmdline_text = ramdisk::file(cmdline::CMDLINE_PATH).and_then(|data| core::str::from_utf8(data).ok());
+    cmdline::init(cmdline_text.unwrap_or(""));
+    logger::init_filters();
+    info!("Kernel command line: {}", cmdline::cmdline().text());
+
+    match ramdisk::file(frame_buffer::FONT_PATH).and_then(frame_buffer::Font::parse_psf1) {
+        Some(font) => {
+            frame_buffer::init(framebuffer, framebuffer_info, font);
+            info!("Frame buffer initialized.");
+        }
+        None => warn!("Font {} is missing from the ramdisk, console output goes to the serial port only.", frame_buffer::FONT_PATH),
+    }
+
+    memory::init(physical_memory_offset, memory_regions);
+    let frame_stats = memory::frame_stats();
+    info!(
+        "Memory Management initialized: {} used, {} free of {} physical frames.",
+        frame_stats.used,
+        frame_stats.free,
+        frame_stats.total
+    );
+
+    allocator::init_heap();
+    info!("Memory Heap Allocator initialized.");
+
+    let acpi_info = acpi::init(rsdp_addr);
+    info!("Advanced Configuration and Power Interface (ACPI) initialized.");
+
+    // before the local APIC, its timer is calibrated against the HPET
+    if let Some(hpet_info) = &acpi_info.hpet {
+        time::hpet::init(hpet_info);
+    }
+
+    gdt::init();
+    info!("Global Descriptor Table (GDT) initialized.");
+    smp::init_boot_cpu();
+
+    interrupts::init_apic(&acpi_info.apic);
+    acpi::init_events();
+    time::init_clock_source();
+    time::tsc::init();
+    logger::set_clock(|| time::Instant::now().since_boot());
+    time::init_wall_clock();
+    logger::set_wall_clock(time::unix_time);
+    info!("Interrupts initialized.");
+
+    task::executor::init();
+    smp::init(&acpi_info.processors);
+}
+
+#[cfg(test)]
+bootloader_api::entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+#[cfg(test)]
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init(boot_info);
+    test_main();
+    interrupts::hlt_loop();
+}
+
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    testing::test_panic_handler(info)
+}
@@ -0,0 +1,258 @@
+use core::fmt::{ self, Write };
+use core::time::Duration;
+use log::{ Level, LevelFilter, Log, Metadata, Record };
+use spinning_top::Spinlock;
+use x86_64::instructions::interrupts;
+use crate::cmdline;
+use crate::time::DateTime;
+
+// number of records kept in the dmesg ring buffer, older records are overwritten
+const RING_BUFFER_SIZE: usize = 256;
+const MAX_TARGET_LENGTH: usize = 32;
+const MAX_MESSAGE_LENGTH: usize = 160;
+const MAX_MODULE_FILTERS: usize = 16;
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+static LOGGER: KernelLogger = KernelLogger::new();
+
+// A log record copied into fixed size buffers, so that logging works before
+// the heap is initialized and from interrupt handlers.
+#[derive(Clone, Copy)]
+pub struct LogEntry {
+    pub level: Level,
+    pub timestamp: Duration,
+    // UNIX time once the wall clock is known
+    pub wall_time: Option<Duration>,
+    target: [u8; MAX_TARGET_LENGTH],
+    target_length: usize,
+    message: [u8; MAX_MESSAGE_LENGTH],
+    message_length: usize,
+}
+
+impl LogEntry {
+    const fn empty() -> Self {
+        LogEntry {
+            level: Level::Trace,
+            timestamp: Duration::ZERO,
+            wall_time: None,
+            target: [0; MAX_TARGET_LENGTH],
+            target_length: 0,
+            message: [0; MAX_MESSAGE_LENGTH],
+            message_length: 0,
+        }
+    }
+    fn new(record: &Record, timestamp: Duration, wall_time: Option<Duration>) -> Self {
+        let mut entry = LogEntry::empty();
+        entry.level = record.level();
+        entry.timestamp = timestamp;
+        entry.wall_time = wall_time;
+        entry.target_length = copy_truncated(&mut entry.target, record.target().as_bytes());
+        let mut message = TruncatingWriter { buffer: &mut entry.message, length: 0 };
+        // the writer never fails, it drops whatever does not fit
+        let _ = message.write_fmt(*record.args());
+        entry.message_length = message.length;
+        entry
+    }
+    pub fn target(&self) -> &str {
+        core::str::from_utf8(&self.target[..self.target_length]).unwrap_or("?")
+    }
+    pub fn message(&self) -> &str {
+        // truncation may have split a multi byte character, keep the valid prefix
+        let message = &self.message[..self.message_length];
+        match core::str::from_utf8(message) {
+            Ok(message) => message,
+            Err(error) => core::str::from_utf8(&message[..error.valid_up_to()]).unwrap(),
+        }
+    }
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.wall_time {
+            Some(wall_time) => write!(f, "[{}.{:06}]", DateTime::from_unix_time(wall_time), wall_time.subsec_micros())?,
+            None => write!(f, "[{:>5}.{:06}]", self.timestamp.as_secs(), self.timestamp.subsec_micros())?,
+        }
+        write!(f, " {:<5} {}: {}", self.level, self.target(), self.message())
+    }
+}
+
+struct RingBuffer {
+    entries: [LogEntry; RING_BUFFER_SIZE],
+    // index of the next entry to write
+    head: usize,
+    length: usize,
+}
+
+impl RingBuffer {
+    fn push(&mut self, entry: LogEntry) {
+        self.entries[self.head] = entry;
+        self.head = (self.head + 1) % RING_BUFFER_SIZE;
+        self.length = (self.length + 1).min(RING_BUFFER_SIZE);
+    }
+    // entries from the oldest to the newest
+    fn iter(&self) -> impl Iterator<Item = &LogEntry> {
+        let start = (self.head + RING_BUFFER_SIZE - self.length) % RING_BUFFER_SIZE;
+        (0..self.length).map(move |i| &self.entries[(start + i) % RING_BUFFER_SIZE])
+    }
+}
+
+struct Filters {
+    default: LevelFilter,
+    modules: [Option<(&'static str, LevelFilter)>; MAX_MODULE_FILTERS],
+}
+
+impl Filters {
+    // the most specific (longest) matching module prefix wins
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .flatten()
+            .filter(|(module, _)| is_module_prefix(module, target))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+    fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .flatten()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+pub struct KernelLogger {
+    filters: Spinlock<Filters>,
+    ring_buffer: Spinlock<RingBuffer>,
+    clock: Spinlock<fn() -> Duration>,
+    wall_clock: Spinlock<fn() -> Option<Duration>>,
+}
+
+impl KernelLogger {
+    const fn new() -> Self {
+        KernelLogger {
+            filters: Spinlock::new(Filters { default: DEFAULT_LEVEL, modules: [None; MAX_MODULE_FILTERS] }),
+            ring_buffer: Spinlock::new(RingBuffer { entries: [LogEntry::empty(); RING_BUFFER_SIZE], head: 0, length: 0 }),
+            clock: Spinlock::new(|| Duration::ZERO),
+            wall_clock: Spinlock::new(|| None),
+        }
+    }
+}
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        interrupts::without_interrupts(|| metadata.level() <= self.filters.lock().level_for(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let (clock, wall_clock) = interrupts::without_interrupts(|| (*self.clock.lock(), *self.wall_clock.lock()));
+        let entry = LogEntry::new(record, clock(), wall_clock());
+        interrupts::without_interrupts(|| self.ring_buffer.lock().push(entry));
+        println!("{entry}");
+    }
+
+    // records are printed right away, only the serial port may still be sending
+    fn flush(&self) {
+        crate::serial::flush();
+    }
+}
+
+pub fn init() {
+    log::set_logger(&LOGGER).expect("Logger already initialized");
+    update_max_level();
+}
+
+// apply the `log` and `log.<module>` options of the kernel command line
+pub fn init_filters() {
+    let cmdline = cmdline::cmdline();
+    set_level(cmdline.log_level);
+    for (module, level) in cmdline.module_log_levels() {
+        if !set_module_level(module, level) {
+            log::warn!("Too many module log filters, ignoring log.{module}={level}");
+        }
+    }
+}
+
+// set the level for every module that has no more specific filter
+pub fn set_level(level: LevelFilter) {
+    interrupts::without_interrupts(|| {
+        LOGGER.filters.lock().default = level;
+    });
+    update_max_level();
+}
+
+// Set the level for a module and its submodules, e.g. `kernel::task`. Returns false if all
+// `MAX_MODULE_FILTERS` filters are taken by other modules.
+pub fn set_module_level(module: &'static str, level: LevelFilter) -> bool {
+    let set = interrupts::without_interrupts(|| {
+        let mut filters = LOGGER.filters.lock();
+        let slot = filters.modules
+            .iter()
+            .position(|filter| matches!(filter, Some((existing, _)) if *existing == module))
+            .or_else(|| filters.modules.iter().position(Option::is_none));
+        match slot {
+            Some(slot) => {
+                filters.modules[slot] = Some((module, level));
+                true
+            }
+            None => false,
+        }
+    });
+    update_max_level();
+    set
+}
+
+// the source of log timestamps, e.g. the uptime once a timer is running
+pub fn set_clock(clock: fn() -> Duration) {
+    interrupts::without_interrupts(|| {
+        *LOGGER.clock.lock() = clock;
+    });
+}
+
+// the source of the date and time of day printed instead of the uptime, as UNIX time
+pub fn set_wall_clock(wall_clock: fn() -> Option<Duration>) {
+    interrupts::without_interrupts(|| {
+        *LOGGER.wall_clock.lock() = wall_clock;
+    });
+}
+
+// call `f` for every record in the ring buffer, from the oldest to the newest
+pub fn for_each_entry(f: impl FnMut(&LogEntry)) {
+    interrupts::without_interrupts(|| {
+        LOGGER.ring_buffer.lock().iter().for_each(f);
+    });
+}
+
+// print the whole ring buffer
+pub fn dump() {
+    for_each_entry(|entry| println!("{entry}"));
+}
+
+fn update_max_level() {
+    let max_level = interrupts::without_interrupts(|| LOGGER.filters.lock().max_level());
+    log::set_max_level(max_level);
+}
+
+fn is_module_prefix(module: &str, target: &str) -> bool {
+    target == module || (target.starts_with(module) && target[module.len()..].starts_with("::"))
+}
+
+fn copy_truncated(buffer: &mut [u8], bytes: &[u8]) -> usize {
+    let length = bytes.len().min(buffer.len());
+    buffer[..length].copy_from_slice(&bytes[..length]);
+    length
+}
+
+struct TruncatingWriter<'a> {
+    buffer: &'a mut [u8],
+    length: usize,
+}
+
+impl Write for TruncatingWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.length += copy_truncated(&mut self.buffer[self.length..], s.as_bytes());
+        Ok(())
+    }
+}
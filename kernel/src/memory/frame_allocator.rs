@@ -0,0 +1,208 @@
+use core::slice;
+use x86_64::{ VirtAddr, PhysAddr };
+use x86_64::structures::paging::{ PhysFrame, Size4KiB, FrameAllocator, FrameDeallocator, PageSize };
+use x86_64::structures::paging::frame::PhysFrameRange;
+
+use bootloader_api::info::{ MemoryRegions, MemoryRegionKind };
+
+const FRAME_SIZE: u64 = Size4KiB::SIZE;
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    pub total: usize,
+    pub used: usize,
+    pub free: usize,
+}
+
+// Physical frame allocator backed by a bitmap, one bit per 4 KiB frame (1 = used).
+// The bitmap itself lives in the first usable region big enough to hold it
+// and is accessed through the bootloader's physical memory mapping.
+pub struct BitmapFrameAllocator {
+    memory_regions: &'static MemoryRegions,
+    bitmap: &'static mut [u64],
+    frame_count: usize,
+    usable_count: usize,
+    free_count: usize,
+    next: usize,
+}
+
+impl BitmapFrameAllocator {
+    pub unsafe fn init(memory_regions: &'static MemoryRegions, physical_memory_offset: VirtAddr) -> Self {
+        let highest_address = usable_ranges(memory_regions)
+            .map(|(_, end)| end)
+            .max()
+            .expect("No usable memory regions");
+        let frame_count = (highest_address / FRAME_SIZE) as usize;
+        let word_count = frame_count.div_ceil(BITS_PER_WORD);
+        let bitmap_size = align_up((word_count * 8) as u64, FRAME_SIZE);
+
+        let (bitmap_start, _) = usable_ranges(memory_regions)
+            .find(|(start, end)| end - start >= bitmap_size)
+            .expect("No usable memory region is large enough for the frame bitmap");
+        let bitmap_ptr = (physical_memory_offset + bitmap_start).as_mut_ptr::<u64>();
+        let bitmap = slice::from_raw_parts_mut(bitmap_ptr, word_count);
+        // everything is used until proven usable
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = BitmapFrameAllocator {
+            memory_regions,
+            bitmap,
+            frame_count,
+            usable_count: 0,
+            free_count: 0,
+            next: 0,
+        };
+        for (start, end) in usable_ranges(memory_regions) {
+            for index in (start / FRAME_SIZE) as usize..(end / FRAME_SIZE) as usize {
+                allocator.clear(index);
+                allocator.usable_count += 1;
+                allocator.free_count += 1;
+            }
+        }
+        // reserve the frames holding the bitmap and the null frame
+        for index in (bitmap_start / FRAME_SIZE) as usize..((bitmap_start + bitmap_size) / FRAME_SIZE) as usize {
+            allocator.reserve(index);
+        }
+        allocator.reserve(0);
+        allocator
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.usable_count,
+            used: self.usable_count - self.free_count,
+            free: self.free_count,
+        }
+    }
+
+    // whether the frame belongs to a usable region, i.e. it is ours to hand out and take back
+    pub fn manages(&self, frame: PhysFrame) -> bool {
+        let address = frame.start_address().as_u64();
+        usable_ranges(self.memory_regions).any(|(start, end)| address >= start && address < end)
+    }
+
+    // Keep a frame that is mapped by other means, e.g. identity mapped, from being handed out.
+    // Returns false if it is not ours or already in use, then it must not be freed on its behalf.
+    pub fn reserve_frame(&mut self, frame: PhysFrame) -> bool {
+        self.manages(frame) && self.reserve((frame.start_address().as_u64() / FRAME_SIZE) as usize)
+    }
+
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrameRange> {
+        if count == 0 || count > self.free_count {
+            return None;
+        }
+        let mut run_start = 0;
+        let mut run_length = 0;
+        for index in 0..self.frame_count {
+            if self.is_used(index) {
+                run_length = 0;
+                continue;
+            }
+            if run_length == 0 {
+                run_start = index;
+            }
+            run_length += 1;
+            if run_length == count {
+                for index in run_start..run_start + count {
+                    self.set(index);
+                }
+                self.free_count -= count;
+                let start = frame_at(run_start);
+                return Some(PhysFrame::range(start, start + count as u64));
+            }
+        }
+        None
+    }
+
+    // for memory that has to be reachable before paging is enabled, e.g. real mode code
+    pub fn allocate_below(&mut self, limit: u64) -> Option<PhysFrame> {
+        // the first frame holds the real mode interrupt vector table
+        let end = ((limit / FRAME_SIZE) as usize).min(self.frame_count);
+        let index = (1..end).find(|&index| !self.is_used(index))?;
+        self.set(index);
+        self.free_count -= 1;
+        Some(frame_at(index))
+    }
+
+    pub unsafe fn deallocate_contiguous(&mut self, range: PhysFrameRange) {
+        for frame in range {
+            self.deallocate_frame(frame);
+        }
+    }
+
+    fn reserve(&mut self, index: usize) -> bool {
+        if index >= self.frame_count || self.is_used(index) {
+            return false;
+        }
+        self.set(index);
+        self.free_count -= 1;
+        true
+    }
+    fn is_used(&self, index: usize) -> bool {
+        self.bitmap[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+    fn set(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+    }
+    fn clear(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] &= !(1 << (index % BITS_PER_WORD));
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        if self.free_count == 0 {
+            return None;
+        }
+        let word_count = self.bitmap.len();
+        let first_word = self.next / BITS_PER_WORD;
+        // start searching from the last allocation and wrap around once
+        for i in 0..word_count {
+            let word_index = (first_word + i) % word_count;
+            let word = self.bitmap[word_index];
+            if word == u64::MAX {
+                continue;
+            }
+            let index = word_index * BITS_PER_WORD + (!word).trailing_zeros() as usize;
+            if index >= self.frame_count {
+                continue;
+            }
+            self.set(index);
+            self.free_count -= 1;
+            self.next = index + 1;
+            return Some(frame_at(index));
+        }
+        None
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let index = (frame.start_address().as_u64() / FRAME_SIZE) as usize;
+        assert!(self.manages(frame), "Deallocating unmanaged frame {frame:?}");
+        assert!(self.is_used(index), "Double free of frame {frame:?}");
+        self.clear(index);
+        self.free_count += 1;
+        if index < self.next {
+            self.next = index;
+        }
+    }
+}
+
+// page aligned [start, end) physical ranges of all usable regions
+fn usable_ranges(memory_regions: &'static MemoryRegions) -> impl Iterator<Item = (u64, u64)> {
+    memory_regions
+        .iter()
+        .filter(|r| r.kind == MemoryRegionKind::Usable)
+        .map(|r| (align_up(r.start, FRAME_SIZE), r.end & !(FRAME_SIZE - 1)))
+        .filter(|(start, end)| start < end)
+}
+
+fn frame_at(index: usize) -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(index as u64 * FRAME_SIZE))
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    (value + align - 1) & !(align - 1)
+}
@@ -0,0 +1,188 @@
+use conquer_once::spin::OnceCell;
+use spinning_top::{ Spinlock, guard::SpinlockGuard };
+use x86_64::{ VirtAddr, PhysAddr };
+use x86_64::structures::paging::{ PageTable, PhysFrame, FrameAllocator, FrameDeallocator, OffsetPageTable, PageTableFlags, Mapper, Page, Size4KiB };
+use x86_64::structures::paging::mapper::{ MapToError, Translate, TranslateResult };
+use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::registers::control::Cr3;
+
+use bootloader_api::info::MemoryRegions;
+
+mod frame_allocator;
+mod tlb;
+
+pub use frame_allocator::FrameStats;
+use frame_allocator::BitmapFrameAllocator;
+pub use tlb::{ shootdown as tlb_shootdown, handle_shootdown as handle_tlb_shootdown };
+
+static MEM_MGR: OnceCell<Spinlock<MemoryManager>> = OnceCell::uninit();
+// an available page table bit, set on pages whose frame the mapping allocated or reserved itself
+const OWNS_FRAME: PageTableFlags = PageTableFlags::BIT_9;
+// the bootloader maps all physical memory at this offset
+static PHYSICAL_MEMORY_OFFSET: OnceCell<VirtAddr> = OnceCell::uninit();
+
+pub struct MemoryManager {
+    mapper: OffsetPageTable<'static>,
+    allocator: BitmapFrameAllocator,
+}
+
+impl MemoryManager {
+    // A free usable frame is marked used until `unmap` frees it again, so that it is not handed out
+    // meanwhile. It has to be reserved before mapping, the page tables may need new frames. A frame
+    // that is already allocated stays with its owner.
+    pub fn identity_map(&mut self, physical_address: u64, flags: Option<PageTableFlags>) {
+        let mut flags = flags.unwrap_or_else(|| { PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE });
+        let physical_address = PhysAddr::new(physical_address);
+        let physical_frame: PhysFrame = PhysFrame::containing_address(physical_address);
+        if self.allocator.reserve_frame(physical_frame) {
+            flags |= OWNS_FRAME;
+        }
+        unsafe {
+            self.mapper.identity_map(physical_frame, flags, &mut self.allocator).expect("Failed to identity map").flush();
+        }
+    }
+    // On failure the pages mapped so far are unmapped again. Nobody used them yet, so no other
+    // CPU needs a TLB shootdown.
+    pub fn range_map(&mut self, start: VirtAddr, size: u64, flags: Option<PageTableFlags>) -> Result<(), MapToError<Size4KiB>> {
+        let end = start + size - 1u64;
+        let heap_start_page = Page::containing_address(start);
+        let heap_end_page = Page::containing_address(end);
+        let page_range = Page::range_inclusive(heap_start_page, heap_end_page);
+        let flags = flags.unwrap_or_else(|| { PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE });
+        for page in page_range {
+            if let Err(error) = self.map_page(page, flags) {
+                for mapped_page in Page::range(heap_start_page, page) {
+                    if let Some(frame) = self.unmap(mapped_page) {
+                        self.free_unmapped_frame(frame);
+                    }
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+    fn map_page(&mut self, page: Page, flags: PageTableFlags) -> Result<(), MapToError<Size4KiB>> {
+        let frame = self.allocator.allocate_frame().ok_or(MapToError::FrameAllocationFailed)?;
+        match unsafe { self.mapper.map_to(page, frame, flags | OWNS_FRAME, &mut self.allocator) } {
+            Ok(flush) => {
+                flush.flush();
+                Ok(())
+            }
+            Err(error) => {
+                unsafe { self.allocator.deallocate_frame(frame) }
+                Err(error)
+            }
+        }
+    }
+    // Returns the frame if the mapping owned it, device memory or a frame allocated by someone else
+    // is not ours to reclaim. It can only be reused once no CPU caches the mapping anymore, see
+    // `free_unmapped_frame`.
+    pub fn unmap(&mut self, page: Page) -> Option<PhysFrame> {
+        let owns_frame = self.owns_frame(page);
+        let (frame, flush) = self.mapper.unmap(page).expect("Failed to unmap");
+        flush.flush();
+        owns_frame.then_some(frame)
+    }
+    pub fn free_unmapped_frame(&mut self, frame: PhysFrame) {
+        unsafe { self.allocator.deallocate_frame(frame) }
+    }
+    fn owns_frame(&self, page: Page) -> bool {
+        match self.mapper.translate(page.start_address()) {
+            TranslateResult::Mapped { flags, .. } => flags.contains(OWNS_FRAME),
+            _ => false,
+        }
+    }
+    // the page keeps owning its frame whatever `flags` are
+    pub fn set_flags(&mut self, page: Page, mut flags: PageTableFlags) {
+        flags.set(OWNS_FRAME, self.owns_frame(page));
+        unsafe {
+            self.mapper.update_flags(page, flags).expect("Failed to update page flags").flush();
+        }
+    }
+    pub fn allocate_frames(&mut self, count: usize) -> Option<PhysFrameRange> {
+        self.allocator.allocate_contiguous(count)
+    }
+    pub fn allocate_frame_below(&mut self, limit: u64) -> Option<PhysFrame> {
+        self.allocator.allocate_below(limit)
+    }
+    /// # Safety
+    ///
+    /// The frames must have been allocated by `allocate_frames` or `allocate_frame_below` and
+    /// must not be used anymore, neither mapped nor referenced by a device.
+    pub unsafe fn deallocate_frames(&mut self, range: PhysFrameRange) {
+        self.allocator.deallocate_contiguous(range);
+    }
+    pub fn frame_stats(&self) -> FrameStats {
+        self.allocator.stats()
+    }
+}
+unsafe impl Send for MemoryManager {}
+unsafe impl Sync for MemoryManager {}
+
+pub fn range_map(start: VirtAddr, size: u64, flags: Option<PageTableFlags>) -> Result<(), MapToError<Size4KiB>> {
+    lock().range_map(start, size, flags)
+}
+pub fn identity_map(physical_address: u64, flags: Option<PageTableFlags>) {
+    lock().identity_map(physical_address, flags);
+}
+// The other CPUs are shot down after the memory manager is unlocked, a CPU waiting for it with
+// interrupts disabled could not take the IPI otherwise. The frame is freed only afterwards.
+pub fn unmap(page: Page) {
+    let frame = lock().unmap(page);
+    tlb::shootdown(Some(page));
+    if let Some(frame) = frame {
+        lock().free_unmapped_frame(frame);
+    }
+}
+pub fn set_flags(page: Page, flags: PageTableFlags) {
+    lock().set_flags(page, flags);
+    tlb::shootdown(Some(page));
+}
+pub fn allocate_frames(count: usize) -> Option<PhysFrameRange> {
+    lock().allocate_frames(count)
+}
+pub fn allocate_frame_below(limit: u64) -> Option<PhysFrame> {
+    lock().allocate_frame_below(limit)
+}
+/// # Safety
+///
+/// See `MemoryManager::deallocate_frames`.
+pub unsafe fn deallocate_frames(range: PhysFrameRange) {
+    lock().deallocate_frames(range);
+}
+pub fn frame_stats() -> FrameStats {
+    lock().frame_stats()
+}
+
+// Also taken with interrupts disabled by the heap allocator, so nothing may wait for the other
+// CPUs, e.g. for a TLB shootdown, while holding it.
+fn lock() -> SpinlockGuard<'static, MemoryManager> {
+    MEM_MGR.get().expect("Failed to get MEM_MGR").lock()
+}
+
+// the address through which physical memory can be accessed without mapping it first
+pub fn physical_to_virtual(physical_address: u64) -> VirtAddr {
+    *PHYSICAL_MEMORY_OFFSET.get().expect("Failed to get physical memory offset") + physical_address
+}
+
+pub fn init(physical_memory_offset: u64, memory_regions: &'static MemoryRegions) {
+    let physical_memory_offset = VirtAddr::new(physical_memory_offset);
+    PHYSICAL_MEMORY_OFFSET.init_once(|| physical_memory_offset);
+    unsafe {
+        let level_4_table = active_level_4_table(physical_memory_offset);
+        let mapper = OffsetPageTable::new(level_4_table, physical_memory_offset);
+        let allocator = BitmapFrameAllocator::init(memory_regions, physical_memory_offset);
+
+        MEM_MGR.init_once(move || Spinlock::new(MemoryManager { mapper, allocator }));
+    }
+}
+
+unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
+    let (level_4_table_frame, _) = Cr3::read();
+
+    let phys = level_4_table_frame.start_address();
+    let virt = physical_memory_offset + phys.as_u64();
+    let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
+
+    &mut *page_table_ptr
+}
@@ -0,0 +1,62 @@
+use core::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use spinning_top::Spinlock;
+use x86_64::VirtAddr;
+use x86_64::instructions::tlb;
+use x86_64::structures::paging::Page;
+
+use crate::{ cpu_local, interrupts, smp };
+use crate::interrupts::{ InterruptIndex, IpiTarget };
+
+// shootdown of the whole TLB instead of a single page
+const FLUSH_ALL: u64 = u64::MAX;
+
+// one shootdown at a time and the page it flushes
+static SHOOTDOWN: Spinlock<()> = Spinlock::new(());
+static SHOOTDOWN_ADDRESS: AtomicU64 = AtomicU64::new(FLUSH_ALL);
+
+cpu_local! {
+    // set by the initiator for every other CPU, cleared by the CPU once it flushed
+    static PENDING: AtomicBool = AtomicBool::new(false);
+}
+
+// Flush `page`, or the whole TLB for `None`, on every other CPU and wait until all of them did.
+// The caller flushes its own TLB. A CPU that spins on a lock with interrupts disabled does not
+// take the IPI, so the caller must not hold such a lock, e.g. the memory manager. CPUs waiting
+// to start a shootdown of their own answer the current one meanwhile.
+pub fn shootdown(page: Option<Page>) {
+    let cpu_count = smp::cpu_count();
+    if cpu_count == 1 {
+        return;
+    }
+    let _shootdown = loop {
+        if let Some(shootdown) = SHOOTDOWN.try_lock() {
+            break shootdown;
+        }
+        handle_shootdown();
+        core::hint::spin_loop();
+    };
+    SHOOTDOWN_ADDRESS.store(page.map_or(FLUSH_ALL, |page| page.start_address().as_u64()), Ordering::SeqCst);
+    let cpu_id = smp::cpu_id();
+    let other_cpus = || (0..cpu_count).filter(move |&cpu| cpu != cpu_id);
+    for cpu in other_cpus() {
+        PENDING.for_cpu(cpu).store(true, Ordering::SeqCst);
+    }
+    interrupts::send_ipi(IpiTarget::AllExcludingSelf, InterruptIndex::TlbShootdown);
+    while other_cpus().any(|cpu| PENDING.for_cpu(cpu).load(Ordering::SeqCst)) {
+        core::hint::spin_loop();
+    }
+}
+
+// called from the TLB shootdown IPI handler and by CPUs waiting to start a shootdown
+pub fn handle_shootdown() {
+    // nothing to answer before the per-CPU areas of the other CPUs exist
+    if smp::cpu_count() == 1 || !PENDING.get().load(Ordering::SeqCst) {
+        return;
+    }
+    match SHOOTDOWN_ADDRESS.load(Ordering::SeqCst) {
+        FLUSH_ALL => tlb::flush_all(),
+        address => tlb::flush(VirtAddr::new(address)),
+    }
+    smp::current().stats().tlb_shootdowns.fetch_add(1, Ordering::Relaxed);
+    PENDING.get().store(false, Ordering::SeqCst);
+}
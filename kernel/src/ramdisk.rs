@@ -0,0 +1,146 @@
+use core::slice;
+use conquer_once::spin::OnceCell;
+
+// The ramdisk is a ustar archive built from the `initrd` directory by the host build script,
+// the bootloader loads it into memory and maps it, so every file is a `'static` slice.
+// refer to https://www.gnu.org/software/tar/manual/html_node/Standard.html
+const BLOCK_SIZE: usize = 512;
+const NAME_RANGE: (usize, usize) = (0, 100);
+const SIZE_RANGE: (usize, usize) = (124, 136);
+const TYPE_FLAG_OFFSET: usize = 156;
+const MAGIC_RANGE: (usize, usize) = (257, 262);
+const PREFIX_RANGE: (usize, usize) = (345, 500);
+const USTAR_MAGIC: &[u8] = b"ustar";
+// GNU tar stores a path too long for the header in an entry of its own right before the file
+const GNU_LONG_NAME: u8 = b'L';
+
+static RAMDISK: OnceCell<&'static [u8]> = OnceCell::uninit();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Directory,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct File {
+    // the path inside the archive is split in two parts by ustar, `prefix/name`
+    prefix: &'static str,
+    name: &'static str,
+    pub kind: FileKind,
+    pub data: &'static [u8],
+}
+
+impl File {
+    // compare against a path relative to the `initrd` directory, e.g. `fonts/Uni2-Fixed16.psf`
+    pub fn has_path(&self, path: &str) -> bool {
+        let path = path.trim_start_matches('/');
+        if self.prefix.is_empty() {
+            return self.name == path;
+        }
+        match path.strip_prefix(self.prefix) {
+            Some(rest) => rest.strip_prefix('/') == Some(self.name),
+            None => false,
+        }
+    }
+    pub fn prefix(&self) -> &'static str {
+        self.prefix
+    }
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+pub struct Files {
+    archive: &'static [u8],
+    offset: usize,
+}
+
+impl Iterator for Files {
+    type Item = File;
+
+    fn next(&mut self) -> Option<File> {
+        let mut long_name = None;
+        loop {
+            let header = self.archive.get(self.offset..self.offset + BLOCK_SIZE)?;
+            // the archive ends with zero filled blocks
+            if header[MAGIC_RANGE.0..MAGIC_RANGE.1] != *USTAR_MAGIC {
+                return None;
+            }
+            let size = parse_octal(&header[SIZE_RANGE.0..SIZE_RANGE.1])?;
+            let data_start = self.offset + BLOCK_SIZE;
+            let data = self.archive.get(data_start..data_start + size)?;
+            self.offset = data_start + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+
+            let kind = match header[TYPE_FLAG_OFFSET] {
+                GNU_LONG_NAME => {
+                    long_name = Some(parse_str(data));
+                    continue;
+                }
+                b'0' | 0 => FileKind::File,
+                b'5' => FileKind::Directory,
+                _ => FileKind::Other,
+            };
+            // the long name is the whole path
+            let (prefix, name) = match long_name {
+                Some(name) => ("", name),
+                None => (parse_str(&header[PREFIX_RANGE.0..PREFIX_RANGE.1]), parse_str(&header[NAME_RANGE.0..NAME_RANGE.1])),
+            };
+            return Some(File {
+                prefix: prefix.trim_start_matches("./").trim_end_matches('/'),
+                name: name.trim_start_matches("./").trim_end_matches('/'),
+                kind,
+                data,
+            });
+        }
+    }
+}
+
+pub fn init(ramdisk_address: Option<u64>, ramdisk_length: u64) -> bool {
+    let ramdisk_address = match ramdisk_address {
+        Some(address) if ramdisk_length > 0 => address,
+        _ => {
+            return false;
+        }
+    };
+    let archive = unsafe { slice::from_raw_parts(ramdisk_address as *const u8, ramdisk_length as usize) };
+    RAMDISK.init_once(|| archive);
+    true
+}
+
+pub fn files() -> Files {
+    Files {
+        archive: RAMDISK.get().copied().unwrap_or(&[]),
+        offset: 0,
+    }
+}
+
+pub fn file(path: &str) -> Option<&'static [u8]> {
+    files()
+        .find(|file| file.kind == FileKind::File && file.has_path(path))
+        .map(|file| file.data)
+}
+
+fn parse_octal(field: &[u8]) -> Option<usize> {
+    let mut value = 0;
+    for &byte in field.iter().skip_while(|&&byte| byte == b' ') {
+        match byte {
+            b'0'..=b'7' => {
+                value = value * 8 + (byte - b'0') as usize;
+            }
+            b' ' | 0 => {
+                break;
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+    Some(value)
+}
+
+fn parse_str(field: &'static [u8]) -> &'static str {
+    let length = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..length]).unwrap_or("")
+}
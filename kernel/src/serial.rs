@@ -0,0 +1,47 @@
+use core::fmt;
+use lazy_static::lazy_static;
+use spinning_top::Spinlock;
+use uart_16550::SerialPort;
+use x86_64::instructions::port::Port;
+
+// I/O port of the first serial port (COM1)
+const COM1: u16 = 0x3f8;
+const LINE_STATUS_REGISTER: u16 = COM1 + 5;
+// set once the last byte left the shift register
+const TRANSMITTER_EMPTY: u8 = 1 << 6;
+
+lazy_static! {
+    pub static ref SERIAL1: Spinlock<SerialPort> = {
+        let mut serial_port = unsafe { SerialPort::new(COM1) };
+        serial_port.init();
+        Spinlock::new(serial_port)
+    };
+}
+
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        SERIAL1.lock().write_fmt(args).expect("Printing to serial failed");
+    });
+}
+
+// wait until everything written so far was sent, e.g. before powering off
+pub fn flush() {
+    let mut line_status = Port::<u8>::new(LINE_STATUS_REGISTER);
+    while unsafe { line_status.read() } & TRANSMITTER_EMPTY == 0 {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,134 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+use core::time::Duration;
+use acpi::platform::{ ProcessorInfo, ProcessorState };
+use log::{ info, warn };
+use x86_64::VirtAddr;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{ Page, PageTableFlags, PhysFrame };
+
+use crate::{ gdt, interrupts, memory, cmdline };
+use crate::task::executor::Executor;
+use crate::time::Instant;
+
+mod trampoline;
+mod percpu;
+
+use trampoline::Trampoline;
+pub use percpu::{ current, try_current, cpu_id, cpu, PerCpu, CpuStats, CpuLocal, MAX_CPUS };
+
+// the startup IPI can only name a page below 1 MiB
+const TRAMPOLINE_LIMIT: u64 = 0x10_0000;
+// kernel stacks of the application processors, each one above an unmapped guard page
+const AP_STACKS_START: u64 = 0x_5555_5555_0000;
+const AP_STACK_SIZE: u64 = 16 * 4096;
+const GUARD_PAGE_SIZE: u64 = 4096;
+// refer to the MultiProcessor Specification B.4 Application Processor Startup
+const INIT_DELAY: Duration = Duration::from_millis(10);
+const STARTUP_IPI_TIMEOUT: Duration = Duration::from_millis(1);
+const STARTUP_TIMEOUT: Duration = Duration::from_millis(100);
+
+// CPUs running kernel code, the boot processor is CPU 0 and the others are numbered as they start
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(1);
+// set by an application processor once it no longer needs the trampoline
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::Acquire)
+}
+
+// the per-CPU area of the boot processor, after the GDT is loaded
+pub fn init_boot_cpu() {
+    percpu::init(0);
+}
+
+// Start every application processor the MADT lists, one after the other since they share the
+// trampoline. Needs a running clock for the delays of the INIT-SIPI-SIPI sequence.
+pub fn init(processor_info: &ProcessorInfo) {
+    let processors: Vec<u32> = processor_info.application_processors
+        .iter()
+        .filter(|processor| processor.state != ProcessorState::Disabled)
+        .map(|processor| processor.local_apic_id)
+        .take(MAX_CPUS - 1)
+        .collect();
+    if processors.is_empty() || !cmdline::cmdline().smp {
+        return;
+    }
+
+    // the trampoline loads CR3 before it leaves real mode
+    let page_table = Cr3::read().0.start_address().as_u64();
+    if page_table > u32::MAX as u64 {
+        warn!("Page tables above 4 GiB can not be loaded by the AP trampoline, application processors are not started");
+        return;
+    }
+    let frame = match memory::allocate_frame_below(TRAMPOLINE_LIMIT) {
+        Some(frame) => frame,
+        None => {
+            warn!("No free memory below 1 MiB for the AP trampoline, application processors are not started");
+            return;
+        }
+    };
+    // the trampoline keeps running at its physical address right after it enabled paging
+    let trampoline_address = frame.start_address().as_u64();
+    memory::identity_map(trampoline_address, Some(PageTableFlags::PRESENT | PageTableFlags::WRITABLE));
+    let mut trampoline = unsafe { Trampoline::install(frame, page_table as u32, ap_main) };
+
+    // every processor gets a stack slot of its own, the CPU index of one that failed to start is
+    // reused but its stack may still be mapped
+    for (stack_slot, apic_id) in processors.into_iter().enumerate() {
+        let cpu = cpu_count();
+        unsafe { trampoline.prepare(allocate_stack(stack_slot), cpu as u64) };
+        if !start_ap(&trampoline, apic_id) {
+            warn!("Application processor with APIC ID {apic_id} did not start");
+            // keep it from running into the trampoline later on
+            interrupts::send_init_ipi(apic_id);
+        }
+    }
+    // the identity mapping does not own the frame, it is freed once no CPU can cache the mapping
+    memory::unmap(Page::containing_address(VirtAddr::new(trampoline_address)));
+    unsafe { memory::deallocate_frames(PhysFrame::range(frame, frame + 1)) };
+    info!("Symmetric multiprocessing initialized: {} CPUs online.", cpu_count());
+}
+
+fn start_ap(trampoline: &Trampoline, apic_id: u32) -> bool {
+    AP_STARTED.store(false, Ordering::Release);
+    interrupts::send_init_ipi(apic_id);
+    wait_for(INIT_DELAY, || false);
+    // the second startup IPI is only needed if the first one got lost
+    for timeout in [STARTUP_IPI_TIMEOUT, STARTUP_TIMEOUT] {
+        interrupts::send_startup_ipi(apic_id, trampoline.page());
+        if wait_for(timeout, || AP_STARTED.load(Ordering::Acquire)) {
+            return true;
+        }
+    }
+    false
+}
+
+fn allocate_stack(stack_slot: usize) -> VirtAddr {
+    let stack_start = VirtAddr::new(AP_STACKS_START + (stack_slot as u64) * (GUARD_PAGE_SIZE + AP_STACK_SIZE) + GUARD_PAGE_SIZE);
+    memory::range_map(stack_start, AP_STACK_SIZE, None).expect("Failed to map AP stack");
+    stack_start + AP_STACK_SIZE
+}
+
+fn wait_for(timeout: Duration, condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    condition()
+}
+
+// called by the trampoline in long mode, on the stack allocated for this CPU, then runs tasks like the boot processor
+extern "C" fn ap_main(cpu: u64) -> ! {
+    gdt::init_ap();
+    percpu::init(cpu as usize);
+    interrupts::init_ap();
+    CPU_COUNT.fetch_add(1, Ordering::AcqRel);
+    AP_STARTED.store(true, Ordering::Release);
+    info!("CPU {cpu} online.");
+    x86_64::instructions::interrupts::enable();
+    Executor::new().run();
+}
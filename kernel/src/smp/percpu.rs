@@ -0,0 +1,158 @@
+use alloc::boxed::Box;
+use core::arch::asm;
+use core::sync::atomic::{ AtomicU32, AtomicU64, Ordering };
+use conquer_once::spin::OnceCell;
+use spinning_top::Spinlock;
+use x2apic::lapic::LocalApic;
+use x86_64::VirtAddr;
+use x86_64::registers::model_specific::{ GsBase, KernelGsBase };
+
+// highest number of CPUs the kernel runs on, the others are not started
+pub const MAX_CPUS: usize = 64;
+const NO_TASK: u64 = u64::MAX;
+
+// The data every CPU keeps to itself, found through the GS base of the CPU. The first field
+// points to the structure itself, so `gs:[0]` yields its address.
+//
+// There is no user mode, so interrupt handlers never need `swapgs`. Both IA32_GS_BASE and
+// IA32_KERNEL_GS_BASE point to the per-CPU area.
+#[repr(C)]
+pub struct PerCpu {
+    self_pointer: *const PerCpu,
+    cpu_id: usize,
+    apic_id: AtomicU32,
+    local_apic: OnceCell<Spinlock<LocalApic>>,
+    current_task: AtomicU64,
+    stats: CpuStats,
+}
+
+// only the CPU it belongs to changes the structure, other CPUs only read it
+unsafe impl Sync for PerCpu {}
+
+#[derive(Debug, Default)]
+pub struct CpuStats {
+    pub interrupts: AtomicU64,
+    pub tlb_shootdowns: AtomicU64,
+}
+
+impl PerCpu {
+    pub fn cpu_id(&self) -> usize {
+        self.cpu_id
+    }
+
+    pub fn apic_id(&self) -> u32 {
+        self.apic_id.load(Ordering::Relaxed)
+    }
+
+    // the local APIC of this CPU, access it with interrupts disabled, interrupt handlers use it for the end of interrupt
+    pub fn local_apic(&self) -> &Spinlock<LocalApic> {
+        self.local_apic.get().expect("Cannot get Local APIC")
+    }
+
+    pub fn try_local_apic(&self) -> Option<&Spinlock<LocalApic>> {
+        self.local_apic.get()
+    }
+
+    pub fn set_local_apic(&self, local_apic: LocalApic, apic_id: u32) {
+        self.apic_id.store(apic_id, Ordering::Relaxed);
+        self.local_apic.init_once(move || Spinlock::new(local_apic));
+    }
+
+    // the id of the task being polled on this CPU
+    pub fn current_task(&self) -> Option<u64> {
+        match self.current_task.load(Ordering::Relaxed) {
+            NO_TASK => None,
+            task_id => Some(task_id),
+        }
+    }
+
+    pub fn set_current_task(&self, task_id: Option<u64>) {
+        self.current_task.store(task_id.unwrap_or(NO_TASK), Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> &CpuStats {
+        &self.stats
+    }
+}
+
+// every per-CPU area, indexed by CPU id
+static CPUS: [OnceCell<&'static PerCpu>; MAX_CPUS] = [const { OnceCell::uninit() }; MAX_CPUS];
+
+// Create the per-CPU area of the CPU this runs on. Loading a segment selector into GS clears the
+// GS base, so this has to come after the GDT is loaded.
+pub fn init(cpu_id: usize) {
+    let per_cpu: &'static mut PerCpu = Box::leak(Box::new(PerCpu {
+        self_pointer: core::ptr::null(),
+        cpu_id,
+        apic_id: AtomicU32::new(0),
+        local_apic: OnceCell::uninit(),
+        current_task: AtomicU64::new(NO_TASK),
+        stats: CpuStats::default(),
+    }));
+    per_cpu.self_pointer = per_cpu;
+    let address = VirtAddr::from_ptr(per_cpu as *const PerCpu);
+    GsBase::write(address);
+    KernelGsBase::write(address);
+    CPUS[cpu_id].init_once(|| per_cpu);
+}
+
+// the per-CPU area of the CPU this runs on
+pub fn current() -> &'static PerCpu {
+    let per_cpu: *const PerCpu;
+    unsafe {
+        asm!("mov {}, gs:[0]", out(reg) per_cpu, options(nostack, preserves_flags, readonly));
+        &*per_cpu
+    }
+}
+
+pub fn cpu_id() -> usize {
+    current().cpu_id
+}
+
+// for code that may run before `init`, e.g. a panic early during boot
+pub fn try_current() -> Option<&'static PerCpu> {
+    match GsBase::read().is_null() {
+        true => None,
+        false => Some(current()),
+    }
+}
+
+// the per-CPU area of any started CPU
+pub fn cpu(cpu_id: usize) -> Option<&'static PerCpu> {
+    CPUS.get(cpu_id)?.get().copied()
+}
+
+// A variable with one instance per CPU, declared with `cpu_local!`. Every CPU sees its own,
+// `for_cpu` reaches the one of another CPU.
+pub struct CpuLocal<T: 'static> {
+    values: [T; MAX_CPUS],
+}
+
+impl<T: 'static> CpuLocal<T> {
+    pub const fn new(values: [T; MAX_CPUS]) -> CpuLocal<T> {
+        CpuLocal { values }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.values[cpu_id()]
+    }
+
+    pub fn for_cpu(&self, cpu_id: usize) -> &T {
+        &self.values[cpu_id]
+    }
+}
+
+// cpu_local! {
+//     static COUNTER: AtomicU64 = AtomicU64::new(0);
+// }
+// the initializer has to be a constant, it is evaluated once for every CPU
+#[macro_export]
+macro_rules! cpu_local {
+    ($($(#[$attribute:meta])* $visibility:vis static $name:ident: $type:ty = $init:expr;)*) => {
+        $(
+            $(#[$attribute])*
+            $visibility static $name: $crate::smp::CpuLocal<$type> =
+                $crate::smp::CpuLocal::new([const { $init }; $crate::smp::MAX_CPUS]);
+        )*
+    };
+}
@@ -0,0 +1,158 @@
+use core::arch::global_asm;
+use core::mem::size_of;
+use core::ptr::addr_of;
+use x86_64::VirtAddr;
+use x86_64::structures::paging::PhysFrame;
+
+use crate::memory;
+
+// An application processor starts in real mode at the page the startup IPI names, with CS set
+// to that page. The trampoline switches straight to long mode with the page tables of the boot
+// processor, a temporary GDT and the stack and entry point written into its data block, then
+// calls the entry point with the CPU index. Everything it touches is addressed relative to the
+// page it was copied to. refer to https://wiki.osdev.org/Entering_Long_Mode_Directly
+global_asm!(
+    r#"
+.section .text.ap_trampoline, "ax"
+.code16
+.global ap_trampoline_start
+ap_trampoline_start:
+.Lstart:
+    cli
+    cld
+    mov ax, cs
+    mov ds, ax
+
+    // physical address extension, then the page tables and long mode with no-execute pages
+    mov eax, cr4
+    or eax, 1 << 5
+    mov cr4, eax
+    mov eax, dword ptr [.Ldata_offset + 8]
+    mov cr3, eax
+    mov ecx, 0xc0000080
+    rdmsr
+    or eax, (1 << 8) | (1 << 11)
+    wrmsr
+
+    // protected mode and paging at once, the far jump loads the 64-bit code segment
+    lgdt [.Ldata_offset + 46]
+    mov eax, cr0
+    or eax, (1 << 31) | (1 << 16) | 1
+    mov cr0, eax
+    // jmp far dword ptr [data], with the offset and selector at the start of the data block
+    .byte 0x66, 0xff, 0x2e
+    .word .Ldata_offset
+
+.code64
+.Llong_mode:
+    mov ax, 0x10
+    mov ds, ax
+    mov es, ax
+    mov fs, ax
+    mov gs, ax
+    mov ss, ax
+    mov rsp, qword ptr [rip + .Ldata + 56]
+    mov rdi, qword ptr [rip + .Ldata + 72]
+    call qword ptr [rip + .Ldata + 64]
+    ud2
+
+.balign 8
+.Ldata:
+    .long .Llong_mode - .Lstart
+    .word 0x08
+    .space 80 - 6
+.global ap_trampoline_end
+ap_trampoline_end:
+// real mode addresses are offsets into the trampoline
+.set .Ldata_offset, .Ldata - .Lstart
+.code64
+.text
+"#
+);
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_end: u8;
+}
+
+// the data block at the end of the trampoline, the offsets are hard coded in the code above
+#[repr(C)]
+struct TrampolineData {
+    // linear address of `.Llong_mode`, assembled relative to the start of the trampoline
+    long_mode_offset: u32,
+    long_mode_selector: u16,
+    _padding: u16,
+    page_table: u32,
+    _padding2: u32,
+    gdt: [u64; 3],
+    _padding3: [u16; 3],
+    gdt_limit: u16,
+    gdt_base: u64,
+    stack_top: u64,
+    entry: u64,
+    cpu: u64,
+}
+
+// null descriptor, 64-bit code segment, data segment
+const GDT: [u64; 3] = [0, 0x00af_9a00_0000_ffff, 0x00cf_9200_0000_ffff];
+const GDT_OFFSET: u64 = 16;
+
+pub struct Trampoline {
+    frame: PhysFrame,
+}
+
+impl Trampoline {
+    // copy the trampoline to `frame` below 1 MiB, the page has to be identity mapped
+    pub unsafe fn install(frame: PhysFrame, page_table: u32, entry: extern "C" fn(u64) -> !) -> Trampoline {
+        let code = trampoline_code();
+        assert!(code.len() <= 4096, "AP trampoline does not fit into a page");
+        let destination: *mut u8 = memory::physical_to_virtual(frame.start_address().as_u64()).as_mut_ptr();
+        destination.copy_from_nonoverlapping(code.as_ptr(), code.len());
+
+        let trampoline = Trampoline { frame };
+        let base = trampoline.base() as u32;
+        let data = &mut *trampoline.data();
+        data.long_mode_offset += base;
+        data.page_table = page_table;
+        data.gdt = GDT;
+        data.gdt_limit = (size_of::<[u64; 3]>() - 1) as u16;
+        data.gdt_base = base as u64 + data_offset() as u64 + GDT_OFFSET;
+        data.entry = entry as usize as u64;
+        trampoline
+    }
+
+    // the stack and the argument of the next CPU to start
+    pub unsafe fn prepare(&mut self, stack_top: VirtAddr, cpu: u64) {
+        let data = &mut *self.data();
+        data.stack_top = stack_top.as_u64();
+        data.cpu = cpu;
+    }
+
+    // the vector of the startup IPI
+    pub fn page(&self) -> u8 {
+        (self.base() >> 12) as u8
+    }
+
+    fn base(&self) -> u64 {
+        self.frame.start_address().as_u64()
+    }
+
+    fn data(&self) -> *mut TrampolineData {
+        memory::physical_to_virtual(self.base() + data_offset() as u64).as_mut_ptr()
+    }
+}
+
+// taking the address of an extern static only became safe in newer compilers
+#[allow(unused_unsafe)]
+fn trampoline_code() -> &'static [u8] {
+    unsafe {
+        let start = addr_of!(ap_trampoline_start);
+        let end = addr_of!(ap_trampoline_end);
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    }
+}
+
+// the data block is the end of the trampoline
+fn data_offset() -> usize {
+    trampoline_code().len() - size_of::<TrampolineData>()
+}
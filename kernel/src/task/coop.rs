@@ -0,0 +1,68 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{ AtomicBool, AtomicU32, Ordering };
+use core::task::{ Context, Poll };
+
+use crate::{ cpu_local, smp };
+
+// Tasks are never preempted, a task whose stream always has items ready would keep its CPU
+// forever. Every poll may make progress this many times before it has to yield.
+pub const POLL_BUDGET: u32 = 32;
+
+cpu_local! {
+    static BUDGET: AtomicU32 = AtomicU32::new(POLL_BUDGET);
+    // set once the task was made to yield, using up the budget exactly does not count
+    static EXHAUSTED: AtomicBool = AtomicBool::new(false);
+}
+
+// called by the executor before it polls a task
+pub(super) fn reset_budget() {
+    BUDGET.get().store(POLL_BUDGET, Ordering::Relaxed);
+    EXHAUSTED.get().store(false, Ordering::Relaxed);
+}
+
+// whether the task being polled on this CPU ran out of budget and has to yield
+pub(super) fn budget_exhausted() -> bool {
+    smp::current().current_task().is_some() && EXHAUSTED.get().load(Ordering::Relaxed)
+}
+
+// Take one unit of the budget of the task being polled. Once it is used up the task is woken
+// again and `Pending` is returned, so that it yields to the other tasks. Streams call this
+// before handing out an item, outside of a task it always succeeds.
+pub fn poll_budget(cx: &mut Context) -> Poll<()> {
+    if smp::current().current_task().is_none() {
+        return Poll::Ready(());
+    }
+    let budget = BUDGET.get();
+    let remaining = budget.load(Ordering::Relaxed);
+    if remaining == 0 {
+        // before waking, `enqueue` looks at it to queue the task one class lower
+        EXHAUSTED.get().store(true, Ordering::Relaxed);
+        cx.waker().wake_by_ref();
+        return Poll::Pending;
+    }
+    budget.store(remaining - 1, Ordering::Relaxed);
+    Poll::Ready(())
+}
+
+// let the other queued tasks run before the current task continues
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
@@ -1,92 +1,280 @@
-use super::{ Task, TaskId };
-use alloc::{ task::Wake, collections::BTreeMap, sync::Arc };
+use super::{ Task, Priority, coop };
+use alloc::{ task::Wake, sync::Arc };
+use conquer_once::spin::OnceCell;
+use core::future::Future;
+use core::pin::pin;
+use core::sync::atomic::{ AtomicBool, Ordering };
 use core::task::{ Waker, Context, Poll };
-use crossbeam_queue::ArrayQueue;
+use core::time::Duration;
+use crossbeam_queue::SegQueue;
+use spinning_top::Spinlock;
 use x86_64::instructions::interrupts;
+use crate::{ cpu_local, smp };
+use crate::interrupts::{ send_ipi, InterruptIndex, IpiTarget };
+use crate::time::{ timer, Instant };
 
-const TASK_QUEUE_SIZE: usize = 100;
+// every this many picks the lowest class is served first
+pub const STARVATION_INTERVAL: u64 = 16;
+// tasks polled before the executor looks at its timers again
+const TASKS_PER_ROUND: usize = 64;
 
-struct TaskWaker {
-    task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+// Every CPU runs an executor on its own run queue. A woken task goes to the queue of the CPU that
+// woke it, CPUs that ran out of work steal from the others and halt when there is nothing left.
+cpu_local! {
+    static RUN_QUEUE: OnceCell<RunQueue> = OnceCell::uninit();
+    // set while the CPU is about to halt, it is woken with an IPI when work arrives
+    static IDLE: AtomicBool = AtomicBool::new(false);
+    static STATS: Spinlock<ExecutorStats> = Spinlock::new(ExecutorStats::new());
 }
 
-impl TaskWaker {
-    pub fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(
-            Arc::new(Self {
-                task_id,
-                task_queue,
-            })
-        )
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        enqueue(self);
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        enqueue(self.clone());
     }
+}
 
-    fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect("Task queue is full ");
+// also called from interrupt handlers, the heap allocator is safe to use there
+pub(super) fn enqueue(task: Arc<Task>) {
+    if task.queued.swap(true, Ordering::AcqRel) {
+        return;
     }
+    // CPUs that did not start an executor yet leave their tasks to the boot processor
+    let cpu_id = smp::cpu_id();
+    let run_queue = match RUN_QUEUE.get().get() {
+        Some(run_queue) => run_queue,
+        None => RUN_QUEUE.for_cpu(0).get().expect("Executor not initialized"),
+    };
+    let priority = task.queue_priority();
+    run_queue.push(task, priority);
+    wake_idle_cpu(cpu_id);
 }
-impl Wake for TaskWaker {
+
+// wakes the future passed to `block_on`, from any CPU
+struct BlockOnWaker {
+    cpu_id: usize,
+    woken: AtomicBool,
+}
+
+impl Wake for BlockOnWaker {
     fn wake(self: Arc<Self>) {
-        self.wake_task();
+        self.wake_by_ref();
     }
     fn wake_by_ref(self: &Arc<Self>) {
-        self.wake_task();
+        self.woken.store(true, Ordering::SeqCst);
+        if self.cpu_id != smp::cpu_id() && IDLE.for_cpu(self.cpu_id).load(Ordering::SeqCst) {
+            if let Some(per_cpu) = smp::cpu(self.cpu_id) {
+                send_ipi(IpiTarget::Cpu(per_cpu.apic_id()), InterruptIndex::Wakeup);
+            }
+        }
     }
 }
 
+// an unbounded queue per priority class, a burst of woken tasks must not be dropped
+struct RunQueue {
+    classes: [SegQueue<Arc<Task>>; Priority::COUNT],
+}
+
+impl RunQueue {
+    fn new() -> RunQueue {
+        RunQueue {
+            classes: [const { SegQueue::new() }; Priority::COUNT],
+        }
+    }
+    fn push(&self, task: Arc<Task>, priority: Priority) {
+        self.classes[priority as usize].push(task);
+    }
+    fn pop(&self, lowest_first: bool) -> Option<Arc<Task>> {
+        if lowest_first {
+            self.classes.iter().rev().find_map(SegQueue::pop)
+        } else {
+            self.classes.iter().find_map(SegQueue::pop)
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.classes.iter().all(SegQueue::is_empty)
+    }
+}
+
+// the waking CPU is busy, an idle one can steal the task sooner
+fn wake_idle_cpu(cpu_id: usize) {
+    let idle_cpu = (0..smp::cpu_count())
+        .filter(|&cpu| cpu != cpu_id)
+        .find(|&cpu| IDLE.for_cpu(cpu).load(Ordering::SeqCst));
+    if let Some(per_cpu) = idle_cpu.and_then(smp::cpu) {
+        send_ipi(IpiTarget::Cpu(per_cpu.apic_id()), InterruptIndex::Wakeup);
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExecutorStats {
+    pub polls: u64,
+    pub completed_tasks: u64,
+    pub stolen_tasks: u64,
+    // time spent polling tasks and halted waiting for interrupts
+    pub busy: Duration,
+    pub idle: Duration,
+}
+
+impl ExecutorStats {
+    const fn new() -> ExecutorStats {
+        ExecutorStats { polls: 0, completed_tasks: 0, stolen_tasks: 0, busy: Duration::ZERO, idle: Duration::ZERO }
+    }
+}
+
+// the executor of the CPU it was created on
 pub struct Executor {
-    tasks: BTreeMap<TaskId, Task>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
-    waker_cache: BTreeMap<TaskId, Waker>,
+    cpu_id: usize,
+    run_queue: &'static RunQueue,
+    picks: u64,
+}
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::new()
+    }
 }
 impl Executor {
     pub fn new() -> Self {
+        let cpu_id = smp::cpu_id();
+        let run_queue = RUN_QUEUE.get();
+        // the boot processor may have created its queue in `init` already
+        let _ = run_queue.try_init_once(RunQueue::new);
         Executor {
-            tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(TASK_QUEUE_SIZE)),
-            waker_cache: BTreeMap::new(),
-        }
-    }
-    pub fn spawn(&mut self, task: Task) {
-        let task_id = task.id;
-        if self.tasks.insert(task.id, task).is_some() {
-            panic!("task with same ID already in tasks");
+            cpu_id,
+            run_queue: run_queue.get().expect("Failed to create run queue"),
+            picks: 0,
         }
-        self.task_queue.push(task_id).expect("queue full");
     }
+    // a bounded round, tasks that keep waking themselves must not hold off the timers
     fn run_ready_tasks(&mut self) {
-        while let Some(task_id) = self.task_queue.pop() {
-            let task = match self.tasks.get_mut(&task_id) {
+        for _ in 0..TASKS_PER_ROUND {
+            let task = match self.next_task() {
                 Some(task) => task,
                 None => {
+                    return;
+                }
+            };
+            task.queued.store(false, Ordering::Release);
+            // another CPU still polls it, it was woken again meanwhile
+            let mut future = match task.future.try_lock() {
+                Some(future) => future,
+                None => {
+                    enqueue(task.clone());
                     continue;
                 }
             };
-            let waker = self.waker_cache.entry(task_id).or_insert_with(|| { TaskWaker::new(task_id, self.task_queue.clone()) });
-
-            let mut context = Context::from_waker(waker);
-
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    self.tasks.remove(&task_id);
-                    self.waker_cache.remove(&task_id);
+            if task.aborted.load(Ordering::Acquire) && future.take().is_some() {
+                super::remove_task(task.id);
+                continue;
+            }
+            let poll = match future.as_mut() {
+                Some(future) => {
+                    let waker = Waker::from(task.clone());
+                    let mut context = Context::from_waker(&waker);
+                    let poll_start = Instant::now();
+                    coop::reset_budget();
+                    smp::current().set_current_task(Some(task.id.0));
+                    let poll = future.as_mut().poll(&mut context);
+                    let budget_exhausted = coop::budget_exhausted();
+                    smp::current().set_current_task(None);
+                    let run_time = poll_start.elapsed();
+                    let mut task_stats = task.stats.lock();
+                    task_stats.polls += 1;
+                    task_stats.run_time += run_time;
+                    if budget_exhausted {
+                        task_stats.budget_yields += 1;
+                    }
+                    let mut stats = STATS.get().lock();
+                    stats.busy += run_time;
+                    stats.polls += 1;
+                    poll
+                }
+                // woken after it completed
+                None => {
+                    continue;
                 }
-                Poll::Pending => {}
+            };
+            if let Poll::Ready(()) = poll {
+                future.take();
+                super::remove_task(task.id);
+                STATS.get().lock().completed_tasks += 1;
             }
         }
     }
-    pub fn sleep_if_idle(&self) {
+    // the own queue first, then one task from the queue of another CPU
+    fn next_task(&mut self) -> Option<Arc<Task>> {
+        self.picks = (self.picks + 1) % STARVATION_INTERVAL;
+        let lowest_first = self.picks == 0;
+        if let Some(task) = self.run_queue.pop(lowest_first) {
+            return Some(task);
+        }
+        let cpu_count = smp::cpu_count();
+        let task = (1..cpu_count)
+            .map(|offset| (self.cpu_id + offset) % cpu_count)
+            .filter_map(|cpu| RUN_QUEUE.for_cpu(cpu).get())
+            .find_map(|run_queue| run_queue.pop(lowest_first))?;
+        STATS.get().lock().stolen_tasks += 1;
+        Some(task)
+    }
+    fn has_work(&self) -> bool {
+        (0..smp::cpu_count()).filter_map(|cpu| RUN_QUEUE.for_cpu(cpu).get()).any(|run_queue| !run_queue.is_empty())
+    }
+    // A task queued after the idle flag is set sends a wakeup IPI, which is held pending until
+    // interrupts are enabled again and ends the halt.
+    pub fn sleep_if_idle(&mut self) {
+        self.sleep_unless(|| false);
+    }
+    // `woken` is checked with interrupts disabled as well, like the run queues
+    fn sleep_unless(&mut self, woken: impl Fn() -> bool) {
         interrupts::disable();
-        if self.task_queue.is_empty() {
+        IDLE.get().store(true, Ordering::SeqCst);
+        if !self.has_work() && !woken() {
+            let idle_start = Instant::now();
             interrupts::enable_and_hlt();
+            STATS.get().lock().idle += idle_start.elapsed();
         } else {
             interrupts::enable();
         }
+        IDLE.get().store(false, Ordering::SeqCst);
+    }
+    pub fn stats(&self) -> ExecutorStats {
+        *STATS.get().lock()
+    }
+    // Run tasks on this CPU until `future` completes, e.g. in tests, which are not tasks that
+    // could await it. `future` itself is polled outside of any task.
+    pub fn block_on<F: Future>(&mut self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        let block_on_waker = Arc::new(BlockOnWaker { cpu_id: self.cpu_id, woken: AtomicBool::new(true) });
+        let waker = Waker::from(block_on_waker.clone());
+        let mut context = Context::from_waker(&waker);
+        loop {
+            if block_on_waker.woken.swap(false, Ordering::SeqCst) {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                    return output;
+                }
+            }
+            self.run_ready_tasks();
+            timer::expire_timers();
+            self.sleep_unless(|| block_on_waker.woken.load(Ordering::SeqCst));
+        }
     }
     pub fn run(&mut self) -> ! {
         loop {
             self.run_ready_tasks();
+            timer::expire_timers();
             self.sleep_if_idle();
         }
     }
-}
\ No newline at end of file
+}
+
+// the run queue of the boot processor, tasks can be woken before its executor runs
+pub fn init() {
+    RUN_QUEUE.for_cpu(0).init_once(RunQueue::new);
+}
+
+// the executor statistics of a CPU
+pub fn stats(cpu_id: usize) -> ExecutorStats {
+    *STATS.for_cpu(cpu_id).lock()
+}
@@ -0,0 +1,94 @@
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{ AtomicBool, Ordering };
+use core::task::{ Context, Poll };
+use futures_util::task::AtomicWaker;
+use spinning_top::Spinlock;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{ TaskId, abort };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    // the task was aborted before it completed
+    Aborted,
+}
+
+// the result of a task, shared between the task and its handle
+pub(super) struct JoinState<T> {
+    finished: AtomicBool,
+    output: Spinlock<Option<Result<T, JoinError>>>,
+    waker: AtomicWaker,
+}
+
+impl<T> JoinState<T> {
+    pub(super) fn new() -> JoinState<T> {
+        JoinState {
+            finished: AtomicBool::new(false),
+            output: Spinlock::new(None),
+            waker: AtomicWaker::new(),
+        }
+    }
+
+    fn finish(&self, output: Result<T, JoinError>) {
+        if self.finished.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        without_interrupts(|| *self.output.lock() = Some(output));
+        self.waker.wake();
+    }
+}
+
+// finishes the task as aborted when its future is dropped before it completed
+struct AbortGuard<T>(Arc<JoinState<T>>);
+
+impl<T> Drop for AbortGuard<T> {
+    fn drop(&mut self) {
+        self.0.finish(Err(JoinError::Aborted));
+    }
+}
+
+// the future of a spawned task, it hands the output of `future` to the join handle
+pub(super) async fn run<F: Future>(future: F, state: Arc<JoinState<F::Output>>) {
+    let guard = AbortGuard(state);
+    let output = future.await;
+    guard.0.finish(Ok(output));
+}
+
+// Resolves to the output of a spawned task. Dropping the handle detaches the task, it keeps running.
+pub struct JoinHandle<T> {
+    id: TaskId,
+    state: Arc<JoinState<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub(super) fn new(id: TaskId, state: Arc<JoinState<T>>) -> JoinHandle<T> {
+        JoinHandle { id, state }
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.finished.load(Ordering::Acquire)
+    }
+
+    // the handle then resolves to `JoinError::Aborted`, unless the task completed before
+    pub fn abort(&self) {
+        abort(self.id);
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<T, JoinError>> {
+        self.state.waker.register(cx.waker());
+        match without_interrupts(|| self.state.output.lock().take()) {
+            Some(output) => Poll::Ready(output),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use conquer_once::spin::OnceCell;
+use log::{ info, warn };
 use crossbeam_queue::ArrayQueue;
 use pc_keyboard::{ Keyboard, layouts, ScancodeSet1, HandleControl, DecodedKey };
 use core::{ pin::Pin, task::{ Poll, Context } };
-use futures_util::{ stream::Stream, task::AtomicWaker, StreamExt };
+use futures_util::{ ready, stream::Stream, task::AtomicWaker, StreamExt };
+
+use super::poll_budget;
 
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 static WAKER: AtomicWaker = AtomicWaker::new();
@@ -23,6 +26,8 @@ impl Stream for ScancodeStream {
     type Item = u8;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        // a full queue must not keep the task from yielding
+        ready!(poll_budget(cx));
         let queue = SCANCODE_QUEUE.try_get().expect("Scancode queue not initialized");
         if let Some(scancode) = queue.pop() {
             return Poll::Ready(Some(scancode));
@@ -41,12 +46,12 @@ impl Stream for ScancodeStream {
 pub fn add_scancode(scancode: u8) {
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if let Err(_) = queue.push(scancode) {
-            println!("WARNING: scancode queue full; dropping keyboard input");
+            warn!("scancode queue full; dropping keyboard input");
         } else {
             WAKER.wake();
         }
     } else {
-        println!("WARNING: scancode queue uninitialized");
+        warn!("scancode queue uninitialized");
     }
 }
 
@@ -54,7 +59,7 @@ pub async fn print_keypresses() {
     let mut scancodes = ScancodeStream::new();
     let mut keyboard = Keyboard::new(ScancodeSet1::new(), layouts::Us104Key, HandleControl::Ignore);
 
-    println!("Keyboard Task Started.");
+    info!("Keyboard Task Started.");
 
     while let Some(scancode) = scancodes.next().await {
         if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
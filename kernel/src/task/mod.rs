@@ -1,14 +1,27 @@
-use core::task::{ Context, Poll };
 use core::{ future::Future, pin::Pin };
-use core::sync::atomic::{ AtomicU64, Ordering };
-use alloc::boxed::Box;
+use core::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use core::time::Duration;
+use alloc::{ boxed::Box, collections::BTreeMap, sync::{ Arc, Weak } };
+use spinning_top::Spinlock;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::smp;
 
 pub mod executor;
 pub mod keyboard;
 pub mod mouse;
+pub mod power;
+mod join;
+mod coop;
+
+pub use join::{ JoinHandle, JoinError };
+pub use coop::{ poll_budget, yield_now, YieldNow, POLL_BUDGET };
+
+// every task that did not complete yet, to abort it by id
+static TASKS: Spinlock<BTreeMap<TaskId, Weak<Task>>> = Spinlock::new(BTreeMap::new());
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+pub struct TaskId(u64);
 
 impl TaskId {
     fn new() -> Self {
@@ -17,19 +30,125 @@ impl TaskId {
     }
 }
 
-pub struct Task {
+// Every run queue has a class per priority. The executor takes from the highest class with a
+// queued task, but now and then serves the lowest one first so that no class starves.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    // interactive tasks, e.g. the ones handling input
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    pub const COUNT: usize = 3;
+
+    // the class a task is queued in after it used up its poll budget
+    fn lower(self) -> Priority {
+        match self {
+            Priority::High => Priority::Normal,
+            Priority::Normal | Priority::Low => Priority::Low,
+        }
+    }
+}
+
+// per task accounting of the time it held a CPU
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TaskStats {
+    pub priority: Priority,
+    pub polls: u64,
+    pub run_time: Duration,
+    // polls that ended because the task used up its budget
+    pub budget_yields: u64,
+}
+
+// A task is shared between the run queues of all CPUs, whichever CPU takes it from a queue
+// polls it. The future is gone once the task completed.
+struct Task {
     id: TaskId,
-    future: Pin<Box<dyn Future<Output = ()>>>,
+    future: Spinlock<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+    // set while the task waits in a run queue, so that it is queued only once however often it is woken
+    queued: AtomicBool,
+    // the executor drops the future of an aborted task instead of polling it
+    aborted: AtomicBool,
+    priority: Priority,
+    stats: Spinlock<TaskStats>,
 }
 
 impl Task {
-    pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
+    fn new(priority: Priority, future: impl Future<Output = ()> + Send + 'static) -> Task {
         Task {
             id: TaskId::new(),
-            future: Box::pin(future),
+            future: Spinlock::new(Some(Box::pin(future))),
+            queued: AtomicBool::new(false),
+            aborted: AtomicBool::new(false),
+            priority,
+            stats: Spinlock::new(TaskStats { priority, ..TaskStats::default() }),
         }
     }
-    fn poll(&mut self, context: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(context)
+
+    // A task that used up its budget is queued one class lower for its next poll, so that a busy
+    // task can not delay the others of its class.
+    fn queue_priority(&self) -> Priority {
+        if smp::current().current_task() == Some(self.id.0) && coop::budget_exhausted() {
+            self.priority.lower()
+        } else {
+            self.priority
+        }
     }
-}
\ No newline at end of file
+}
+
+// Run `future` as a task on any CPU. Works from running tasks and from interrupt handlers, the
+// task is queued on the calling CPU.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    spawn_with_priority(Priority::Normal, future)
+}
+
+pub fn spawn_with_priority<F>(priority: Priority, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let state = Arc::new(join::JoinState::new());
+    let task = Arc::new(Task::new(priority, join::run(future, state.clone())));
+    let task_id = task.id;
+    without_interrupts(|| TASKS.lock().insert(task_id, Arc::downgrade(&task)));
+    executor::enqueue(task);
+    JoinHandle::new(task_id, state)
+}
+
+// Stop a task at its next await point, its join handle resolves to `JoinError::Aborted`.
+// Returns false if the task already completed.
+pub fn abort(task_id: TaskId) -> bool {
+    let task = match without_interrupts(|| TASKS.lock().get(&task_id).and_then(Weak::upgrade)) {
+        Some(task) => task,
+        None => {
+            return false;
+        }
+    };
+    task.aborted.store(true, Ordering::Release);
+    executor::enqueue(task);
+    true
+}
+
+// the task being polled on this CPU
+pub fn current_task_id() -> Option<TaskId> {
+    smp::current().current_task().map(TaskId)
+}
+
+// the accounting of a task that did not complete yet
+pub fn task_stats(task_id: TaskId) -> Option<TaskStats> {
+    let task = without_interrupts(|| TASKS.lock().get(&task_id).and_then(Weak::upgrade))?;
+    let stats = *task.stats.lock();
+    Some(stats)
+}
+
+// called by the executor once the future of a task is gone
+fn remove_task(task_id: TaskId) {
+    without_interrupts(|| TASKS.lock().remove(&task_id));
+}
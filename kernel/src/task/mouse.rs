@@ -1,7 +1,10 @@
 use core::{ pin::Pin, task::{ Poll, Context } };
 use conquer_once::spin::OnceCell;
+use log::{ info, warn };
 use crossbeam_queue::ArrayQueue;
-use futures_util::{ task::AtomicWaker, Stream, StreamExt };
+use futures_util::{ ready, task::AtomicWaker, Stream, StreamExt };
+
+use super::poll_budget;
 use ps2_mouse::{ Mouse, MouseState };
 
 static MOUSE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
@@ -12,12 +15,12 @@ static MOUSE_QUEUE_SIZE: usize = 200;
 pub fn add_packet(packet: u8) {
     if let Ok(queue) = MOUSE_QUEUE.try_get() {
         if let Err(_) = queue.push(packet) {
-            println!("WARNING: mouse queue full; dropping mouse input");
+            warn!("mouse queue full; dropping mouse input");
         } else {
             WAKER.wake();
         }
     } else {
-        println!("WARNING: Mouse queue not initialized");
+        warn!("Mouse queue not initialized");
     }
 }
 
@@ -35,6 +38,8 @@ impl Stream for PacketStream {
     type Item = u8;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        // a full queue must not keep the task from yielding
+        ready!(poll_budget(cx));
         let queue = MOUSE_QUEUE.try_get().expect("Mouse queue is not initialized");
         if let Some(packet) = queue.pop() {
             return Poll::Ready(Some(packet));
@@ -63,7 +68,7 @@ pub async fn print_mouse_position() {
     let mut packets = PacketStream::new();
     let mut mouse = Mouse::new();
     mouse.set_on_complete(handler);
-    println!("Mouse Task Started.");
+    info!("Mouse Task Started.");
 
     while let Some(packet) = packets.next().await {
         mouse.process_packet(packet);
@@ -0,0 +1,9 @@
+use log::info;
+use crate::acpi;
+
+// shut down once the power button is pressed, e.g. with `system_powerdown` in the QEMU monitor
+pub async fn handle_power_button() {
+    acpi::power_button().await;
+    info!("Power button pressed.");
+    acpi::shutdown();
+}
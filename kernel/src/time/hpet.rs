@@ -0,0 +1,236 @@
+use core::ptr::{ read_volatile, write_volatile };
+use core::sync::atomic::{ AtomicU64, Ordering };
+use core::time::Duration;
+use acpi::HpetInfo;
+use conquer_once::spin::OnceCell;
+use spinning_top::Spinlock;
+use log::{ info, warn };
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::paging::PageTableFlags;
+use x2apic::ioapic::IrqFlags;
+
+use crate::{ memory, interrupts };
+
+// High Precision Event Timer, a memory mapped main counter running at a fixed frequency of at least
+// 10 MHz and a set of comparators that raise an interrupt when the counter reaches their value.
+// refer to https://wiki.osdev.org/HPET
+const CAPABILITIES_REGISTER: u64 = 0x000;
+const CONFIGURATION_REGISTER: u64 = 0x010;
+const MAIN_COUNTER_REGISTER: u64 = 0x0f0;
+const ENABLE: u64 = 1 << 0;
+const COUNTER_64BIT: u64 = 1 << 13;
+const TIMER_LEVEL_TRIGGERED: u64 = 1 << 1;
+const TIMER_INTERRUPT_ENABLE: u64 = 1 << 2;
+const TIMER_PERIODIC: u64 = 1 << 3;
+const TIMER_ROUTE_SHIFT: u64 = 9;
+const TIMER_ROUTE_MASK: u64 = 0x1f << TIMER_ROUTE_SHIFT;
+const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+// the first comparator is used for one-shot interrupts
+const ONESHOT_TIMER: u64 = 0;
+// the ISA interrupts are left to the legacy devices
+const FIRST_ROUTED_GSI: u32 = 16;
+
+static HPET: OnceCell<Hpet> = OnceCell::uninit();
+// A 32 bit main counter wraps after about 43 s at 100 MHz, the upper half is counted here. The
+// counter has to be read at least once every half wrap to notice it, see `update_extended_counter`.
+static EXTENDED_COUNTER: AtomicU64 = AtomicU64::new(0);
+// the I/O APIC input the one-shot comparator is wired to
+static ONESHOT_GSI: OnceCell<u32> = OnceCell::uninit();
+static ONESHOT_HANDLER: Spinlock<Option<fn()>> = Spinlock::new(None);
+
+struct Hpet {
+    base: u64,
+    period_fs: u64,
+    counter_64bit: bool,
+    comparators: u8,
+}
+
+impl Hpet {
+    unsafe fn read(&self, register: u64) -> u64 {
+        read_volatile((self.base + register) as *const u64)
+    }
+    unsafe fn write(&self, register: u64, value: u64) {
+        write_volatile((self.base + register) as *mut u64, value);
+    }
+    fn counter(&self) -> u64 {
+        let counter = unsafe { self.read(MAIN_COUNTER_REGISTER) };
+        if self.counter_64bit {
+            return counter;
+        }
+        let low = counter & 0xffff_ffff;
+        let extend = |last: u64| {
+            let value = (last & !0xffff_ffff) | low;
+            match value < last {
+                // another reader already stored a newer value
+                true if last - value < 1 << 31 => Some(last),
+                true => Some(value + (1 << 32)),
+                false => Some(value),
+            }
+        };
+        let last = EXTENDED_COUNTER.fetch_update(Ordering::Relaxed, Ordering::Relaxed, extend).unwrap();
+        extend(last).unwrap()
+    }
+    fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        Duration::from_nanos(((ticks as u128) * (self.period_fs as u128) / 1_000_000) as u64)
+    }
+    fn duration_to_ticks(&self, duration: Duration) -> u64 {
+        (duration.as_nanos() * 1_000_000 / (self.period_fs as u128)) as u64
+    }
+    fn timer_configuration_register(timer: u64) -> u64 {
+        0x100 + 0x20 * timer
+    }
+    fn timer_comparator_register(timer: u64) -> u64 {
+        0x108 + 0x20 * timer
+    }
+}
+
+pub fn init(hpet_info: &HpetInfo) {
+    memory::identity_map(
+        hpet_info.base_address as u64,
+        Some(PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE | PageTableFlags::NO_CACHE)
+    );
+    let mut hpet = Hpet {
+        base: hpet_info.base_address as u64,
+        period_fs: 0,
+        counter_64bit: false,
+        comparators: hpet_info.num_comparators(),
+    };
+    unsafe {
+        let capabilities = hpet.read(CAPABILITIES_REGISTER);
+        // the upper half holds the counter period in femtoseconds
+        hpet.period_fs = capabilities >> 32;
+        hpet.counter_64bit = capabilities & COUNTER_64BIT != 0;
+        if hpet.period_fs == 0 {
+            warn!("HPET reports a zero counter period, not using it");
+            return;
+        }
+        // restart the main counter from zero so that it doubles as time since boot
+        let configuration = hpet.read(CONFIGURATION_REGISTER) & !ENABLE;
+        hpet.write(CONFIGURATION_REGISTER, configuration);
+        hpet.write(MAIN_COUNTER_REGISTER, 0);
+        hpet.write(CONFIGURATION_REGISTER, configuration | ENABLE);
+    }
+    info!(
+        "HPET initialized: {} Hz, {} comparators, {} bit counter",
+        FEMTOSECONDS_PER_SECOND / hpet.period_fs,
+        hpet.comparators,
+        if hpet.counter_64bit { 64 } else { 32 }
+    );
+    HPET.init_once(|| hpet);
+}
+
+// route the one-shot comparator to the I/O APIC, needs the interrupt controllers to be initialized
+pub fn init_interrupts() {
+    let hpet = match HPET.get() {
+        Some(hpet) => hpet,
+        None => {
+            return;
+        }
+    };
+    let configuration_register = Hpet::timer_configuration_register(ONESHOT_TIMER);
+    unsafe {
+        let configuration = hpet.read(configuration_register);
+        // the upper half is a bitmap of the I/O APIC inputs the comparator can be wired to
+        let routes = (configuration >> 32) as u32;
+        let gsi = match (FIRST_ROUTED_GSI..32).chain(0..FIRST_ROUTED_GSI).find(|gsi| routes & (1 << gsi) != 0) {
+            Some(gsi) => gsi,
+            None => {
+                warn!("HPET comparator {ONESHOT_TIMER} can not be routed to the I/O APIC");
+                return;
+            }
+        };
+        if let Err(error) = interrupts::register_irq(gsi, IrqFlags::empty(), handle_interrupt) {
+            warn!("Failed to register GSI {gsi} of the HPET: {error:?}");
+            return;
+        }
+        // edge triggered one-shot mode, the interrupt stays disabled until a deadline is set
+        let configuration = configuration & !(TIMER_ROUTE_MASK | TIMER_LEVEL_TRIGGERED | TIMER_INTERRUPT_ENABLE | TIMER_PERIODIC);
+        hpet.write(configuration_register, configuration | ((gsi as u64) << TIMER_ROUTE_SHIFT));
+        ONESHOT_GSI.init_once(|| gsi);
+        info!("HPET comparator {ONESHOT_TIMER} routed to GSI {gsi}");
+    }
+}
+
+pub fn is_available() -> bool {
+    HPET.get().is_some()
+}
+
+// main counter ticks per second, zero without an HPET
+pub fn frequency() -> u64 {
+    HPET.get().map_or(0, |hpet| FEMTOSECONDS_PER_SECOND / hpet.period_fs)
+}
+
+pub fn counter() -> u64 {
+    HPET.get().map_or(0, Hpet::counter)
+}
+
+// called on every timer tick, so that reading the counter never waits for half a wrap
+pub fn update_extended_counter() {
+    if let Some(hpet) = HPET.get().filter(|hpet| !hpet.counter_64bit) {
+        hpet.counter();
+    }
+}
+
+// monotonic time since the HPET was initialized
+pub fn now() -> Duration {
+    HPET.get().map_or(Duration::ZERO, |hpet| hpet.ticks_to_duration(hpet.counter()))
+}
+
+// Busy wait for `duration` on the main counter, calls `start` when the wait started so that
+// other clocks can be measured against it. Returns false without an HPET.
+pub fn measure(duration: Duration, start: impl FnOnce()) -> bool {
+    let hpet = match HPET.get() {
+        Some(hpet) => hpet,
+        None => {
+            return false;
+        }
+    };
+    let deadline = hpet.counter() + hpet.duration_to_ticks(duration);
+    start();
+    while hpet.counter() < deadline {
+        core::hint::spin_loop();
+    }
+    true
+}
+
+// Call `handler` from the HPET interrupt after `delay`, replacing an earlier one-shot that did
+// not fire yet. Returns false if the HPET or its interrupt are not available.
+pub fn set_oneshot(delay: Duration, handler: fn()) -> bool {
+    let hpet = match (HPET.get(), ONESHOT_GSI.get()) {
+        (Some(hpet), Some(_)) => hpet,
+        _ => {
+            return false;
+        }
+    };
+    let configuration_register = Hpet::timer_configuration_register(ONESHOT_TIMER);
+    without_interrupts(|| unsafe {
+        let configuration = hpet.read(configuration_register);
+        *ONESHOT_HANDLER.lock() = Some(handler);
+        // the comparator only fires when the counter passes it, never set it in the past
+        let comparator = hpet.counter() + hpet.duration_to_ticks(delay).max(1);
+        hpet.write(Hpet::timer_comparator_register(ONESHOT_TIMER), comparator);
+        hpet.write(configuration_register, configuration | TIMER_INTERRUPT_ENABLE);
+        true
+    })
+}
+
+pub fn cancel_oneshot() {
+    if let Some(hpet) = HPET.get() {
+        let configuration_register = Hpet::timer_configuration_register(ONESHOT_TIMER);
+        without_interrupts(|| unsafe {
+            let configuration = hpet.read(configuration_register);
+            hpet.write(configuration_register, configuration & !TIMER_INTERRUPT_ENABLE);
+            ONESHOT_HANDLER.lock().take();
+        });
+    }
+}
+
+// called on every interrupt of the one-shot comparator
+fn handle_interrupt() -> bool {
+    // release the lock first, the handler may set the next one-shot
+    let handler = ONESHOT_HANDLER.lock().take();
+    if let Some(handler) = handler {
+        handler();
+    }
+    true
+}
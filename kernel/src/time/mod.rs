@@ -0,0 +1,118 @@
+use core::sync::atomic::{ AtomicU64, AtomicU8, Ordering };
+use core::time::Duration;
+use conquer_once::spin::OnceCell;
+use log::{ info, warn };
+
+use crate::cmdline;
+
+pub mod timer;
+pub mod pit;
+pub mod hpet;
+pub mod rtc;
+pub mod tsc;
+mod instant;
+
+pub use timer::{ sleep, sleep_until, Sleep };
+pub use instant::Instant;
+pub use rtc::DateTime;
+
+// incremented by the local APIC timer interrupt, never wraps in practice (584 years at 1 GHz)
+static TICKS: AtomicU64 = AtomicU64::new(0);
+// length of one tick, set when the local APIC timer is programmed
+static TICK_PERIOD_NS: AtomicU64 = AtomicU64::new(0);
+static CLOCK_SOURCE: AtomicU8 = AtomicU8::new(ClockSource::ApicTimer as u8);
+// UNIX time read from the RTC and the instant it was read, the wall clock advances from there
+static WALL_CLOCK_BASE: OnceCell<(Duration, Instant)> = OnceCell::uninit();
+
+// where `uptime` comes from, the timer wheel always counts local APIC timer ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ClockSource {
+    ApicTimer,
+    Hpet,
+}
+
+impl ClockSource {
+    pub fn from_name(name: &str) -> Option<ClockSource> {
+        match name {
+            "apic" => Some(ClockSource::ApicTimer),
+            "hpet" => Some(ClockSource::Hpet),
+            _ => None,
+        }
+    }
+}
+
+// switch to the clock source chosen on the command line if it is available
+pub fn init_clock_source() {
+    let source = cmdline::cmdline().clock;
+    if source == ClockSource::Hpet && !hpet::is_available() {
+        warn!("No HPET available, keeping the local APIC timer as clock source");
+        return;
+    }
+    CLOCK_SOURCE.store(source as u8, Ordering::Relaxed);
+    info!("Clock source: {source:?}");
+}
+
+pub fn clock_source() -> ClockSource {
+    match CLOCK_SOURCE.load(Ordering::Relaxed) {
+        source if source == ClockSource::Hpet as u8 => ClockSource::Hpet,
+        _ => ClockSource::ApicTimer,
+    }
+}
+
+// called from the timer interrupt handler
+pub fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    hpet::update_extended_counter();
+}
+
+pub fn set_tick_period(period: Duration) {
+    TICK_PERIOD_NS.store(period.as_nanos() as u64, Ordering::Relaxed);
+}
+
+pub fn tick_period() -> Duration {
+    Duration::from_nanos(TICK_PERIOD_NS.load(Ordering::Relaxed))
+}
+
+// read the RTC once, needs the clock source and the TSC to be final
+pub fn init_wall_clock() {
+    let instant = Instant::now();
+    let now = rtc::read();
+    if !now.is_valid() {
+        warn!("RTC reports an invalid date {now:?}, the wall clock is wrong");
+    }
+    WALL_CLOCK_BASE.init_once(|| (now.unix_time(), instant));
+    info!("Wall clock: {now} UTC");
+}
+
+// time since 1970-01-01 00:00:00 UTC, `None` before the RTC was read
+pub fn unix_time() -> Option<Duration> {
+    let (unix_time, read_at) = *WALL_CLOCK_BASE.get()?;
+    Some(unix_time + read_at.elapsed())
+}
+
+pub fn wall_clock() -> Option<DateTime> {
+    unix_time().map(DateTime::from_unix_time)
+}
+
+// number of timer interrupts since the local APIC timer was started
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+pub fn uptime() -> Duration {
+    match clock_source() {
+        ClockSource::ApicTimer => ticks_to_duration(ticks()),
+        ClockSource::Hpet => hpet::now(),
+    }
+}
+
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    Duration::from_nanos(ticks.saturating_mul(TICK_PERIOD_NS.load(Ordering::Relaxed)))
+}
+
+// rounds up, so that waiting for the returned number of ticks never waits less than `duration`
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    let period = TICK_PERIOD_NS.load(Ordering::Relaxed).max(1) as u128;
+    duration.as_nanos().div_ceil(period) as u64
+}
@@ -0,0 +1,49 @@
+use core::time::Duration;
+use x86_64::instructions::port::Port;
+
+// Programmable Interval Timer, only channel 2 is used as a reference clock for calibrating
+// other timers, its gate and output are wired to the PC speaker control port.
+// refer to https://wiki.osdev.org/Programmable_Interval_Timer
+pub const PIT_FREQUENCY_HZ: u64 = 1_193_182;
+const CHANNEL_2_DATA_PORT: u16 = 0x42;
+const COMMAND_PORT: u16 = 0x43;
+const SPEAKER_CONTROL_PORT: u16 = 0x61;
+// channel 2, lobyte/hibyte access, mode 0 (interrupt on terminal count), binary
+const CHANNEL_2_ONE_SHOT: u8 = 0b1011_0000;
+const GATE_BIT: u8 = 1 << 0;
+const SPEAKER_BIT: u8 = 1 << 1;
+const OUTPUT_BIT: u8 = 1 << 5;
+// give up if the output never goes high, e.g. when there is no PIT
+const MAX_POLLS: u64 = 100_000_000;
+
+// the longest wait the 16 bit counter allows, about 54.9 ms
+pub const MAX_WAIT: Duration = Duration::from_nanos((u16::MAX as u64) * 1_000_000_000 / PIT_FREQUENCY_HZ);
+
+// Busy wait for `duration` (at most `MAX_WAIT`) on PIT channel 2, calls `start` right after
+// the count down started so that other clocks can be measured against it.
+// Returns false if the PIT did not finish counting.
+pub fn measure(duration: Duration, start: impl FnOnce()) -> bool {
+    let count = (duration.min(MAX_WAIT).as_nanos() as u64) * PIT_FREQUENCY_HZ / 1_000_000_000;
+    let mut speaker_control = Port::<u8>::new(SPEAKER_CONTROL_PORT);
+    let mut command = Port::<u8>::new(COMMAND_PORT);
+    let mut channel_2 = Port::<u8>::new(CHANNEL_2_DATA_PORT);
+    unsafe {
+        // close the gate and silence the speaker while programming the counter
+        let control = speaker_control.read() & !(GATE_BIT | SPEAKER_BIT);
+        speaker_control.write(control);
+        command.write(CHANNEL_2_ONE_SHOT);
+        channel_2.write(count as u8);
+        channel_2.write((count >> 8) as u8);
+        // opening the gate starts the count down
+        speaker_control.write(control | GATE_BIT);
+        start();
+        for _ in 0..MAX_POLLS {
+            if speaker_control.read() & OUTPUT_BIT != 0 {
+                speaker_control.write(control);
+                return true;
+            }
+        }
+        speaker_control.write(control);
+    }
+    false
+}
@@ -0,0 +1,193 @@
+use core::fmt;
+use core::time::Duration;
+use spinning_top::Spinlock;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
+
+// CMOS real-time clock, keeps the date and time of day while the machine is off.
+// refer to https://wiki.osdev.org/CMOS
+const ADDRESS_PORT: u16 = 0x70;
+const DATA_PORT: u16 = 0x71;
+// setting the top bit of the address keeps NMIs disabled while the CMOS is accessed
+const NMI_DISABLE: u8 = 0x80;
+const SECONDS_REGISTER: u8 = 0x00;
+const MINUTES_REGISTER: u8 = 0x02;
+const HOURS_REGISTER: u8 = 0x04;
+const DAY_REGISTER: u8 = 0x07;
+const MONTH_REGISTER: u8 = 0x08;
+const YEAR_REGISTER: u8 = 0x09;
+const STATUS_A_REGISTER: u8 = 0x0a;
+const STATUS_B_REGISTER: u8 = 0x0b;
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const BINARY_MODE: u8 = 1 << 2;
+const HOUR_24_MODE: u8 = 1 << 1;
+const HOUR_PM: u8 = 1 << 7;
+// the RTC only stores two digits of the year
+const CENTURY: u16 = 2000;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+// days between 0000-03-01 and 1970-01-01 in the proleptic Gregorian calendar
+const UNIX_EPOCH_DAYS: u64 = 719_468;
+const DAYS_PER_ERA: u64 = 146_097;
+
+static CMOS: Spinlock<Cmos> = Spinlock::new(Cmos { address: Port::new(ADDRESS_PORT), data: Port::new(DATA_PORT) });
+
+struct Cmos {
+    address: Port<u8>,
+    data: Port<u8>,
+}
+
+impl Cmos {
+    fn read(&mut self, register: u8) -> u8 {
+        unsafe {
+            self.address.write(NMI_DISABLE | register);
+            self.data.read()
+        }
+    }
+    fn update_in_progress(&mut self) -> bool {
+        self.read(STATUS_A_REGISTER) & UPDATE_IN_PROGRESS != 0
+    }
+    fn read_raw(&mut self) -> [u8; 6] {
+        while self.update_in_progress() {
+            core::hint::spin_loop();
+        }
+        [
+            self.read(SECONDS_REGISTER),
+            self.read(MINUTES_REGISTER),
+            self.read(HOURS_REGISTER),
+            self.read(DAY_REGISTER),
+            self.read(MONTH_REGISTER),
+            self.read(YEAR_REGISTER),
+        ]
+    }
+}
+
+// a calendar date and time of day in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+impl DateTime {
+    // dates before 1970 are not supported
+    pub fn from_unix_time(time: Duration) -> DateTime {
+        let seconds = time.as_secs();
+        let seconds_of_day = seconds % SECONDS_PER_DAY;
+        // refer to http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let days = seconds / SECONDS_PER_DAY + UNIX_EPOCH_DAYS;
+        let era = days / DAYS_PER_ERA;
+        let day_of_era = days % DAYS_PER_ERA;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        // months are counted from March so that the leap day is the last day of the year
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+        let year = era * 400 + year_of_era + (month <= 2) as u64;
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day / 60 % 60) as u8,
+            second: (seconds_of_day % 60) as u8,
+            nanosecond: time.subsec_nanos(),
+        }
+    }
+
+    // Fields out of range, e.g. read from an RTC with a dead battery, are saturated to the
+    // nearest valid value instead of wrapping around to a date far in the future.
+    pub fn unix_time(&self) -> Duration {
+        let month = self.month.clamp(1, 12) as u64;
+        let day = self.day.clamp(1, 31) as u64;
+        // refer to http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let year = (self.year.max(1970) as u64) - (month <= 2) as u64;
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * DAYS_PER_ERA + day_of_era - UNIX_EPOCH_DAYS;
+        let seconds = days * SECONDS_PER_DAY + (self.hour as u64) * 3600 + (self.minute as u64) * 60 + (self.second as u64);
+        Duration::new(seconds, self.nanosecond)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.year >= 1970 && (1..=12).contains(&self.month) && (1..=31).contains(&self.day)
+            && self.hour < 24 && self.minute < 60 && self.second < 60
+    }
+
+    pub fn unix_timestamp(&self) -> u64 {
+        self.unix_time().as_secs()
+    }
+
+    // 0 is Sunday
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        ((self.unix_timestamp() / SECONDS_PER_DAY + 4) % 7) as u8
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+// Read the current date and time from the RTC, which is assumed to run in UTC.
+// The registers are read until two reads agree, so that an update in between is not missed.
+pub fn read() -> DateTime {
+    let (raw, status_b) = without_interrupts(|| {
+        let mut cmos = CMOS.lock();
+        let mut raw = cmos.read_raw();
+        loop {
+            let again = cmos.read_raw();
+            if again == raw {
+                break;
+            }
+            raw = again;
+        }
+        (raw, cmos.read(STATUS_B_REGISTER))
+    });
+    let [second, minute, hour, day, month, year] = raw;
+    let decode = |value: u8| if status_b & BINARY_MODE != 0 { value } else { from_bcd(value) };
+    let pm = hour & HOUR_PM != 0;
+    let mut hour = decode(hour & !HOUR_PM);
+    if status_b & HOUR_24_MODE == 0 {
+        // 12 AM is midnight, 12 PM is noon
+        hour = match (hour, pm) {
+            (12, false) => 0,
+            (12, true) => 12,
+            (hour, true) => hour + 12,
+            (hour, false) => hour,
+        };
+    }
+    DateTime {
+        year: CENTURY + (decode(year) as u16),
+        month: decode(month),
+        day: decode(day),
+        hour,
+        minute: decode(minute),
+        second: decode(second),
+        nanosecond: 0,
+    }
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0f)
+}
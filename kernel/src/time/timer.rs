@@ -0,0 +1,133 @@
+use alloc::{ sync::Arc, vec::Vec };
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{ AtomicU64, Ordering };
+use core::task::{ Context, Poll };
+use core::time::Duration;
+use futures_util::task::AtomicWaker;
+use spinning_top::Spinlock;
+
+use super::{ ticks, duration_to_ticks, Instant };
+
+// number of slots in the timer wheel, timers further in the future wait for more rounds in their slot
+const WHEEL_SIZE: usize = 256;
+
+struct TimerEntry {
+    deadline: u64,
+    waker: Arc<AtomicWaker>,
+}
+
+// A hashed timer wheel: a timer with deadline `d` lives in slot `d % WHEEL_SIZE`,
+// so expiring the timers of one tick only looks at one slot.
+struct TimerWheel {
+    slots: [Vec<TimerEntry>; WHEEL_SIZE],
+    // last tick whose slot was expired
+    processed: u64,
+}
+
+static WHEEL: Spinlock<TimerWheel> = Spinlock::new(TimerWheel {
+    slots: [const { Vec::new() }; WHEEL_SIZE],
+    processed: 0,
+});
+
+// earliest deadline in the wheel, lets `expire_timers` return early without taking the lock
+static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+impl TimerWheel {
+    fn insert(&mut self, entry: TimerEntry) {
+        NEXT_DEADLINE.fetch_min(entry.deadline, Ordering::Relaxed);
+        self.slots[(entry.deadline as usize) % WHEEL_SIZE].push(entry);
+    }
+
+    fn expire(&mut self, now: u64) {
+        // another CPU already expired this tick
+        if now <= self.processed {
+            return;
+        }
+        // after a long pause every slot may hold expired timers
+        let first = if now - self.processed >= WHEEL_SIZE as u64 { now + 1 - WHEEL_SIZE as u64 } else { self.processed + 1 };
+        for tick in first..=now {
+            self.slots[(tick as usize) % WHEEL_SIZE].retain(|entry| {
+                if entry.deadline <= now {
+                    entry.waker.wake();
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        self.processed = now;
+        let next_deadline = self.slots
+            .iter()
+            .flatten()
+            .map(|entry| entry.deadline)
+            .min()
+            .unwrap_or(u64::MAX);
+        NEXT_DEADLINE.store(next_deadline, Ordering::Relaxed);
+    }
+}
+
+// Wake every sleeping task whose deadline has passed. Called by the executor after each
+// round of polling, the timer interrupt wakes the CPU from `hlt` so this runs at least once per tick.
+pub fn expire_timers() {
+    let now = ticks();
+    if now < NEXT_DEADLINE.load(Ordering::Relaxed) {
+        return;
+    }
+    // every CPU expires timers, `now` must not be older than what the previous holder processed
+    let mut wheel = WHEEL.lock();
+    wheel.expire(ticks());
+}
+
+pub struct Sleep {
+    deadline: u64,
+    waker: Option<Arc<AtomicWaker>>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if ticks() >= self.deadline {
+            return Poll::Ready(());
+        }
+        match &self.waker {
+            Some(waker) => waker.register(cx.waker()),
+            None => {
+                let waker = Arc::new(AtomicWaker::new());
+                waker.register(cx.waker());
+                WHEEL.lock().insert(TimerEntry { deadline: self.deadline, waker: waker.clone() });
+                self.waker = Some(waker);
+            }
+        }
+        // the deadline may have passed while registering
+        if ticks() >= self.deadline {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        // the wheel entry stays until its deadline but must not wake the task anymore
+        if let Some(waker) = &self.waker {
+            waker.take();
+        }
+    }
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_ticks(duration_to_ticks(duration))
+}
+
+pub fn sleep_ticks(ticks_to_wait: u64) -> Sleep {
+    Sleep {
+        deadline: ticks().saturating_add(ticks_to_wait),
+        waker: None,
+    }
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    sleep(deadline.duration_since(Instant::now()))
+}
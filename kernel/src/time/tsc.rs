@@ -0,0 +1,139 @@
+use core::arch::x86_64::{ __cpuid, _rdtsc, CpuidResult };
+use core::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use core::time::Duration;
+use log::{ info, warn };
+
+use crate::cmdline;
+use super::{ hpet, pit };
+
+// The time stamp counter counts CPU cycles since reset. Only an invariant TSC runs at a constant
+// rate in every power state, so that it can be used as a clock.
+// refer to https://wiki.osdev.org/TSC
+const CALIBRATION_PERIOD: Duration = Duration::from_millis(10);
+// local APIC timer ticks to count when there is no HPET to measure against
+const CALIBRATION_TICKS: u64 = 10;
+// CPUID.01H:ECX
+const TSC_DEADLINE_SUPPORT: u32 = 1 << 24;
+// CPUID.80000007H:EDX
+const INVARIANT_TSC: u32 = 1 << 8;
+
+// cycles per second, zero until calibrated
+static FREQUENCY_HZ: AtomicU64 = AtomicU64::new(0);
+// whether `nanos` may be used as a clock
+static USABLE: AtomicBool = AtomicBool::new(false);
+// TSC value and uptime at calibration, TSC time is counted from there
+static BASE_TSC: AtomicU64 = AtomicU64::new(0);
+static BASE_NANOS: AtomicU64 = AtomicU64::new(0);
+
+// Calibrate the TSC against the HPET or the local APIC timer, needs the timer interrupts running.
+// The TSC is used as clock if it is invariant, or if forced with the `tsc` command line option.
+pub fn init() {
+    let frequency = match hpet::is_available() {
+        true => measure(|period, start| hpet::measure(period, start)),
+        false => measure_against_ticks(),
+    };
+    let frequency = match frequency.or_else(cpuid_frequency) {
+        Some(frequency) => frequency,
+        None => {
+            warn!("Failed to calibrate the TSC");
+            return;
+        }
+    };
+    let invariant = is_invariant();
+    let usable = cmdline::cmdline().tsc.unwrap_or(invariant);
+    // take both values at the same moment, so that TSC time continues the uptime
+    let uptime = super::uptime();
+    BASE_TSC.store(read(), Ordering::Relaxed);
+    BASE_NANOS.store(uptime.as_nanos() as u64, Ordering::Relaxed);
+    FREQUENCY_HZ.store(frequency, Ordering::Relaxed);
+    USABLE.store(usable, Ordering::Release);
+    info!("TSC: {frequency} Hz, invariant: {invariant}, used as clock: {usable}");
+}
+
+pub fn read() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+pub fn frequency() -> u64 {
+    FREQUENCY_HZ.load(Ordering::Relaxed)
+}
+
+pub fn is_invariant() -> bool {
+    cpuid(0x8000_0000).eax >= 0x8000_0007 && cpuid(0x8000_0007).edx & INVARIANT_TSC != 0
+}
+
+pub fn deadline_supported() -> bool {
+    cpuid(0x1).ecx & TSC_DEADLINE_SUPPORT != 0
+}
+
+// nanoseconds since boot, `None` if the TSC is not used as clock
+pub fn nanos() -> Option<u64> {
+    if !USABLE.load(Ordering::Acquire) {
+        return None;
+    }
+    let cycles = read().wrapping_sub(BASE_TSC.load(Ordering::Relaxed));
+    let nanos = (cycles as u128) * 1_000_000_000 / (FREQUENCY_HZ.load(Ordering::Relaxed) as u128);
+    Some(BASE_NANOS.load(Ordering::Relaxed) + nanos as u64)
+}
+
+// Measure the frequency before the local APIC timer runs, against the HPET or the PIT,
+// falling back to the frequency CPUID reports.
+pub fn measure_frequency() -> Option<u64> {
+    let frequency = match hpet::is_available() {
+        true => measure(|period, start| hpet::measure(period, start)),
+        false => measure(|period, start| pit::measure(period, start)),
+    };
+    frequency.or_else(cpuid_frequency)
+}
+
+fn measure(reference: impl FnOnce(Duration, &mut dyn FnMut()) -> bool) -> Option<u64> {
+    let mut start = 0;
+    if !reference(CALIBRATION_PERIOD, &mut || start = read()) {
+        return None;
+    }
+    let elapsed = read() - start;
+    Some(elapsed * 1_000_000_000 / (CALIBRATION_PERIOD.as_nanos() as u64))
+}
+
+fn measure_against_ticks() -> Option<u64> {
+    let period = super::tick_period() * (CALIBRATION_TICKS as u32);
+    if period.is_zero() {
+        return None;
+    }
+    // start on a tick boundary
+    let first_tick = super::ticks() + 1;
+    while super::ticks() < first_tick {
+        x86_64::instructions::hlt();
+    }
+    let start = read();
+    while super::ticks() < first_tick + CALIBRATION_TICKS {
+        x86_64::instructions::hlt();
+    }
+    let elapsed = read() - start;
+    Some(((elapsed as u128) * 1_000_000_000 / period.as_nanos()) as u64)
+}
+
+fn cpuid_frequency() -> Option<u64> {
+    let max_leaf = cpuid(0x0).eax;
+    if max_leaf >= 0x15 {
+        // TSC frequency = crystal clock * numerator / denominator
+        let leaf = cpuid(0x15);
+        if leaf.eax != 0 && leaf.ebx != 0 && leaf.ecx != 0 {
+            return Some((leaf.ecx as u64) * (leaf.ebx as u64) / (leaf.eax as u64));
+        }
+    }
+    if max_leaf >= 0x16 {
+        // processor base frequency in MHz
+        let base_mhz = cpuid(0x16).eax & 0xffff;
+        if base_mhz != 0 {
+            return Some((base_mhz as u64) * 1_000_000);
+        }
+    }
+    None
+}
+
+// `__cpuid` is only safe to call on newer toolchains
+#[allow(unused_unsafe)]
+fn cpuid(leaf: u32) -> CpuidResult {
+    unsafe { __cpuid(leaf) }
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use acpi::platform::address::{ AccessSize, AddressSpace, GenericAddress };
+use bootloader_api::{ entry_point, BootInfo };
+use kernel::acpi::split_event_block;
+
+entry_point!(main, config = &kernel::BOOTLOADER_CONFIG);
+
+fn main(boot_info: &'static mut BootInfo) -> ! {
+    kernel::init(boot_info);
+    test_main();
+    kernel::interrupts::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel::testing::test_panic_handler(info)
+}
+
+fn event_block(address: u64, bit_width: u8) -> GenericAddress {
+    GenericAddress {
+        address_space: AddressSpace::SystemIo,
+        bit_width,
+        bit_offset: 0,
+        access_size: AccessSize::Undefined,
+        address,
+    }
+}
+
+#[test_case]
+fn event_block_is_split_in_status_and_enable() {
+    // a 32 bit PM1 event block, as QEMU has at 0x600
+    let (status, enable) = split_event_block(&event_block(0x600, 32));
+    assert_eq!((status.address, status.bit_width), (0x600, 16));
+    assert_eq!((enable.address, enable.bit_width), (0x602, 16));
+    assert_eq!(enable.address_space, AddressSpace::SystemIo);
+    assert_eq!(enable.access_size, AccessSize::Undefined);
+}
+
+#[test_case]
+fn event_block_halves_follow_the_width() {
+    let (status, enable) = split_event_block(&event_block(0xb000, 64));
+    assert_eq!((status.address, status.bit_width), (0xb000, 32));
+    assert_eq!((enable.address, enable.bit_width), (0xb004, 32));
+}
+
+#[test_case]
+fn access_size_is_narrowed_to_a_half() {
+    let dword_block = GenericAddress { access_size: AccessSize::DWordAccess, ..event_block(0x600, 32) };
+    let (status, enable) = split_event_block(&dword_block);
+    assert_eq!((status.access_size, enable.access_size), (AccessSize::WordAccess, AccessSize::WordAccess));
+
+    // narrow enough already
+    let byte_block = GenericAddress { access_size: AccessSize::ByteAccess, ..event_block(0x600, 32) };
+    let (status, enable) = split_event_block(&byte_block);
+    assert_eq!((status.access_size, enable.access_size), (AccessSize::ByteAccess, AccessSize::ByteAccess));
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{ boxed::Box, vec::Vec };
+use alloc::alloc::{ alloc, dealloc, Layout };
+use core::panic::PanicInfo;
+use bootloader_api::{ entry_point, BootInfo };
+use kernel::allocator::{ HEAP_SIZE, heap_size, heap_used, heap_limit, set_heap_limit };
+use kernel::memory;
+
+entry_point!(main, config = &kernel::BOOTLOADER_CONFIG);
+
+fn main(boot_info: &'static mut BootInfo) -> ! {
+    kernel::init(boot_info);
+    test_main();
+    kernel::interrupts::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn simple_allocation() {
+    let heap_value_1 = Box::new(41);
+    let heap_value_2 = Box::new(13);
+    assert_eq!(*heap_value_1, 41);
+    assert_eq!(*heap_value_2, 13);
+}
+
+#[test_case]
+fn large_vec() {
+    let n = 1000;
+    let mut vec = Vec::new();
+    for i in 0..n {
+        vec.push(i);
+    }
+    assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
+}
+
+#[test_case]
+fn many_boxes() {
+    for i in 0..HEAP_SIZE {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+}
+
+#[test_case]
+fn heap_grows_beyond_initial_size() {
+    let vec: Vec<u8> = alloc::vec![0xab; HEAP_SIZE * 4];
+    assert!(heap_size() > HEAP_SIZE);
+    assert!(vec.iter().all(|&byte| byte == 0xab));
+}
+
+#[test_case]
+fn heap_growth_stops_at_the_page_rounded_limit() {
+    const PAGE_SIZE: usize = 4096;
+    // too large for the slabs, these come straight from the heap
+    let layout = Layout::from_size_align(2 * PAGE_SIZE, 8).unwrap();
+    let size_before = heap_size();
+    let mut allocations = Vec::with_capacity((size_before - heap_used()) / layout.size() + 32);
+    let limit_before = heap_limit();
+    // room for 16 more pages, half a page of the limit can not be used
+    set_heap_limit(size_before + 16 * PAGE_SIZE + PAGE_SIZE / 2);
+    loop {
+        let ptr = unsafe { alloc(layout) };
+        if ptr.is_null() {
+            break;
+        }
+        allocations.push(ptr);
+    }
+    assert!(heap_size() > HEAP_SIZE);
+    assert_eq!(heap_size(), size_before + 16 * PAGE_SIZE);
+
+    for ptr in allocations {
+        unsafe { dealloc(ptr, layout) };
+    }
+    set_heap_limit(limit_before);
+}
+
+#[test_case]
+fn contiguous_frames_are_reclaimed() {
+    let free_before = memory::frame_stats().free;
+    let frames = memory::allocate_frames(16).expect("Failed to allocate contiguous frames");
+    assert_eq!(frames.count(), 16);
+    assert_eq!(memory::frame_stats().free, free_before - 16);
+    unsafe {
+        memory::deallocate_frames(frames);
+    }
+    assert_eq!(memory::frame_stats().free, free_before);
+}
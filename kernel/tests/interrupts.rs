@@ -0,0 +1,153 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{ AtomicU32, Ordering };
+use core::time::Duration;
+use acpi::platform::interrupt::{ InterruptSourceOverride, Polarity, TriggerMode };
+use bootloader_api::{ entry_point, BootInfo };
+use kernel::interrupts::{ self, GsiRange, IrqFlags, IrqError, FIRST_DYNAMIC_VECTOR, DYNAMIC_VECTOR_COUNT };
+use kernel::smp;
+use kernel::time::Instant;
+use x86_64::instructions::interrupts::without_interrupts;
+
+// I/O APIC inputs that no device drives on the QEMU machine
+const SPARE_GSI: u32 = 22;
+const OTHER_SPARE_GSI: u32 = 23;
+
+static DECLINED_CALLS: AtomicU32 = AtomicU32::new(0);
+static HANDLED_CALLS: AtomicU32 = AtomicU32::new(0);
+
+entry_point!(main, config = &kernel::BOOTLOADER_CONFIG);
+
+fn main(boot_info: &'static mut BootInfo) -> ! {
+    kernel::init(boot_info);
+    test_main();
+    kernel::interrupts::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel::testing::test_panic_handler(info)
+}
+
+// the I/O APIC handling `gsi` and its redirection table entry, as `route_gsi` chooses it
+fn io_apic_for(gsi_ranges: &[GsiRange], gsi: u32) -> Option<(usize, u8)> {
+    gsi_ranges.iter().enumerate().find_map(|(index, gsi_range)| Some((index, gsi_range.entry(gsi)?)))
+}
+
+#[test_case]
+fn gsi_range_covers_its_entries() {
+    let gsi_range = GsiRange { gsi_base: 24, max_entry: 23 };
+    assert_eq!(gsi_range.entry(23), None);
+    assert_eq!(gsi_range.entry(24), Some(0));
+    assert_eq!(gsi_range.entry(47), Some(23));
+    assert_eq!(gsi_range.entry(48), None);
+}
+
+#[test_case]
+fn io_apic_is_chosen_by_gsi_base() {
+    let gsi_ranges = [GsiRange { gsi_base: 24, max_entry: 15 }, GsiRange { gsi_base: 0, max_entry: 23 }];
+    assert_eq!(io_apic_for(&gsi_ranges, 2), Some((1, 2)));
+    assert_eq!(io_apic_for(&gsi_ranges, 30), Some((0, 6)));
+    assert_eq!(io_apic_for(&gsi_ranges, 40), None);
+}
+
+#[test_case]
+fn first_io_apic_starts_at_gsi_0() {
+    let gsi_ranges = interrupts::gsi_ranges();
+    assert!(!gsi_ranges.is_empty());
+    assert_eq!(io_apic_for(&gsi_ranges, 0).map(|(_, entry)| entry), Some(0));
+}
+
+#[test_case]
+fn isa_irq_override_changes_gsi_and_flags() {
+    interrupts::init_isa_overrides(&[
+        InterruptSourceOverride { isa_source: 14, global_system_interrupt: 21, polarity: Polarity::ActiveLow, trigger_mode: TriggerMode::Level },
+        InterruptSourceOverride { isa_source: 15, global_system_interrupt: 22, polarity: Polarity::SameAsBus, trigger_mode: TriggerMode::SameAsBus },
+    ]);
+    assert_eq!(interrupts::isa_irq_to_gsi(14, IrqFlags::empty()), (21, IrqFlags::LEVEL_TRIGGERED | IrqFlags::LOW_ACTIVE));
+    // without polarity and trigger mode the defaults of the device stay
+    assert_eq!(interrupts::isa_irq_to_gsi(15, IrqFlags::LOW_ACTIVE), (22, IrqFlags::LOW_ACTIVE));
+}
+
+#[test_case]
+fn isa_irq_without_override_keeps_its_number() {
+    assert_eq!(interrupts::isa_irq_to_gsi(3, IrqFlags::empty()), (3, IrqFlags::empty()));
+}
+
+#[test_case]
+fn invalid_isa_override_is_ignored() {
+    interrupts::init_isa_overrides(&[
+        InterruptSourceOverride { isa_source: 16, global_system_interrupt: 23, polarity: Polarity::ActiveLow, trigger_mode: TriggerMode::Level },
+    ]);
+    assert_eq!(interrupts::isa_irq_to_gsi(16, IrqFlags::empty()), (16, IrqFlags::empty()));
+}
+
+// raise `vector` on this CPU as if the I/O APIC delivered it, interrupts are disabled while the
+// local APIC is locked since the end of interrupt needs it as well
+fn raise_vector(vector: u8) {
+    without_interrupts(|| {
+        let mut local_apic = smp::current().local_apic().lock();
+        // the ID register holds the ID where the ICR destination expects it, in xAPIC and x2APIC mode
+        unsafe {
+            let apic_id = local_apic.id();
+            local_apic.send_ipi(vector, apic_id);
+        }
+    });
+}
+
+fn wait_for(condition: impl Fn() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(100) {
+        if condition() {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    condition()
+}
+
+#[test_case]
+fn irq_lines_get_their_own_dynamic_vector() {
+    let first = interrupts::register_irq(SPARE_GSI, IrqFlags::empty(), || true).expect("Failed to register IRQ");
+    let second = interrupts::register_irq(OTHER_SPARE_GSI, IrqFlags::empty(), || true).expect("Failed to register IRQ");
+    assert_ne!(first.vector, second.vector);
+    for handle in [&first, &second] {
+        assert!((FIRST_DYNAMIC_VECTOR..FIRST_DYNAMIC_VECTOR + DYNAMIC_VECTOR_COUNT as u8).contains(&handle.vector));
+    }
+    // a shared line keeps the trigger mode and polarity of its first handler
+    let mismatch = interrupts::register_irq(SPARE_GSI, IrqFlags::LEVEL_TRIGGERED, || true);
+    assert_eq!(mismatch, Err(IrqError::FlagsMismatch(SPARE_GSI)));
+
+    // the vector of a line is free again once its last handler is gone
+    let first_vector = first.vector;
+    interrupts::unregister_irq(first);
+    interrupts::unregister_irq(second);
+    let again = interrupts::register_irq(SPARE_GSI, IrqFlags::empty(), || true).expect("Failed to register IRQ");
+    assert_eq!(again.vector, first_vector);
+    interrupts::unregister_irq(again);
+}
+
+#[test_case]
+fn shared_irq_line_runs_every_handler() {
+    let declining = interrupts::register_irq(SPARE_GSI, IrqFlags::empty(), || {
+        DECLINED_CALLS.fetch_add(1, Ordering::SeqCst);
+        false
+    }).expect("Failed to register IRQ");
+    let handling = interrupts::register_irq(SPARE_GSI, IrqFlags::empty(), || {
+        HANDLED_CALLS.fetch_add(1, Ordering::SeqCst);
+        true
+    }).expect("Failed to register IRQ");
+    assert_eq!(declining.vector, handling.vector);
+
+    raise_vector(handling.vector);
+    assert!(wait_for(|| HANDLED_CALLS.load(Ordering::SeqCst) == 1));
+    assert_eq!(DECLINED_CALLS.load(Ordering::SeqCst), 1);
+
+    interrupts::unregister_irq(declining);
+    interrupts::unregister_irq(handling);
+}
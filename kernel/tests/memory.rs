@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use bootloader_api::{ entry_point, BootInfo };
+use kernel::memory;
+use x86_64::VirtAddr;
+use x86_64::structures::paging::{ Page, PhysFrame };
+
+// identity mapped frames have to be addressable without colliding with the kernel's mappings
+const LOW_MEMORY_LIMIT: u64 = 0x10_0000;
+
+entry_point!(main, config = &kernel::BOOTLOADER_CONFIG);
+
+fn main(boot_info: &'static mut BootInfo) -> ! {
+    kernel::init(boot_info);
+    test_main();
+    kernel::interrupts::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel::testing::test_panic_handler(info)
+}
+
+fn page_of(frame: PhysFrame) -> Page {
+    Page::containing_address(VirtAddr::new(frame.start_address().as_u64()))
+}
+
+fn free_frame(frame: PhysFrame) {
+    unsafe { memory::deallocate_frames(PhysFrame::range(frame, frame + 1)) };
+}
+
+// a free frame in low memory whose page tables already exist, so that mapping it takes no frames
+fn free_low_frame() -> PhysFrame {
+    let frame = memory::allocate_frame_below(LOW_MEMORY_LIMIT).expect("Failed to allocate a frame below 1 MiB");
+    memory::identity_map(frame.start_address().as_u64(), None);
+    memory::unmap(page_of(frame));
+    free_frame(frame);
+    frame
+}
+
+#[test_case]
+fn frame_stats_add_up() {
+    let stats = memory::frame_stats();
+    assert!(stats.free > 0);
+    assert_eq!(stats.used + stats.free, stats.total);
+}
+
+#[test_case]
+fn allocated_frame_is_freed_again() {
+    let free_before = memory::frame_stats().free;
+    let frame = memory::allocate_frame_below(LOW_MEMORY_LIMIT).expect("Failed to allocate a frame below 1 MiB");
+    assert!(frame.start_address().as_u64() < LOW_MEMORY_LIMIT);
+    // the first frame holds the real mode interrupt vector table
+    assert_ne!(frame.start_address().as_u64(), 0);
+    assert_eq!(memory::frame_stats().free, free_before - 1);
+
+    free_frame(frame);
+    assert_eq!(memory::frame_stats().free, free_before);
+    // the freed frame is handed out again
+    assert_eq!(memory::allocate_frame_below(LOW_MEMORY_LIMIT), Some(frame));
+    free_frame(frame);
+}
+
+#[test_case]
+fn contiguous_frames_are_adjacent() {
+    assert!(memory::allocate_frames(0).is_none());
+    let first = memory::allocate_frames(4).expect("Failed to allocate contiguous frames");
+    let second = memory::allocate_frames(4).expect("Failed to allocate contiguous frames");
+    assert_eq!(first.end - first.start, 4);
+    // the ranges do not overlap
+    assert!(first.end <= second.start || second.end <= first.start);
+    unsafe {
+        memory::deallocate_frames(first);
+        memory::deallocate_frames(second);
+    }
+}
+
+#[test_case]
+fn too_many_contiguous_frames_fail() {
+    let free_before = memory::frame_stats().free;
+    assert!(memory::allocate_frames(free_before + 1).is_none());
+    assert_eq!(memory::frame_stats().free, free_before);
+}
+
+#[test_case]
+fn identity_mapped_free_frame_is_reserved_until_unmapped() {
+    let frame = free_low_frame();
+    let free_before = memory::frame_stats().free;
+    memory::identity_map(frame.start_address().as_u64(), None);
+    assert_eq!(memory::frame_stats().free, free_before - 1);
+
+    memory::unmap(page_of(frame));
+    assert_eq!(memory::frame_stats().free, free_before);
+}
+
+#[test_case]
+fn unmap_keeps_an_allocated_frame_with_its_owner() {
+    let frame = free_low_frame();
+    assert_eq!(memory::allocate_frame_below(frame.start_address().as_u64() + 1), Some(frame));
+    let free_before = memory::frame_stats().free;
+    memory::identity_map(frame.start_address().as_u64(), None);
+    memory::unmap(page_of(frame));
+    assert_eq!(memory::frame_stats().free, free_before);
+
+    // still allocated, so freeing it is no double free
+    free_frame(frame);
+    assert_eq!(memory::frame_stats().free, free_before + 1);
+}
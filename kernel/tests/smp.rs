@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{ AtomicU64, Ordering };
+use bootloader_api::{ entry_point, BootInfo };
+use kernel::{ cpu_local, interrupts, smp };
+
+// the runner boots this test with 4 CPUs
+const CPU_COUNT: usize = 4;
+
+entry_point!(main, config = &kernel::BOOTLOADER_CONFIG);
+
+fn main(boot_info: &'static mut BootInfo) -> ! {
+    kernel::init(boot_info);
+    test_main();
+    kernel::interrupts::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel::testing::test_panic_handler(info)
+}
+
+cpu_local! {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+}
+
+#[test_case]
+fn boot_processor_is_cpu_0() {
+    assert_eq!(smp::cpu_id(), 0);
+    assert_eq!(smp::current().apic_id(), interrupts::apic_id());
+    assert!(smp::cpu_count() >= 1);
+}
+
+#[test_case]
+fn cpu_local_is_per_cpu() {
+    COUNTER.get().fetch_add(1, Ordering::Relaxed);
+    assert_eq!(COUNTER.for_cpu(0).load(Ordering::Relaxed), 1);
+    assert_eq!(COUNTER.for_cpu(1).load(Ordering::Relaxed), 0);
+}
+
+#[test_case]
+fn tlb_shootdown_returns() {
+    let shootdowns = smp::current().stats().tlb_shootdowns.load(Ordering::Relaxed);
+    kernel::memory::tlb_shootdown(None);
+    // the initiating CPU flushes its own TLB, only the others count a shootdown
+    assert_eq!(smp::current().stats().tlb_shootdowns.load(Ordering::Relaxed), shootdowns);
+}
+
+#[test_case]
+fn application_processors_are_started() {
+    assert_eq!(smp::cpu_count(), CPU_COUNT);
+    for cpu in 1..CPU_COUNT {
+        let per_cpu = smp::cpu(cpu).expect("Application processor has no per-CPU area");
+        assert_ne!(per_cpu.apic_id(), smp::current().apic_id());
+    }
+}
@@ -0,0 +1,204 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{ sync::Arc, task::Wake };
+use core::panic::PanicInfo;
+use core::pin::{ pin, Pin };
+use core::future::{ self, Future };
+use core::sync::atomic::{ AtomicBool, AtomicU32, AtomicU64, Ordering };
+use core::task::{ Context, Poll, Waker };
+use core::time::Duration;
+use bootloader_api::{ entry_point, BootInfo };
+use futures_util::task::noop_waker;
+use kernel::smp;
+use kernel::task::{ self, executor::{ Executor, STARVATION_INTERVAL }, JoinError, Priority };
+use kernel::time::Instant;
+
+entry_point!(main, config = &kernel::BOOTLOADER_CONFIG);
+
+fn main(boot_info: &'static mut BootInfo) -> ! {
+    kernel::init(boot_info);
+    test_main();
+    kernel::interrupts::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn yield_now_is_pending_once() {
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut yield_now = pin!(task::yield_now());
+    assert_eq!(yield_now.as_mut().poll(&mut context), Poll::Pending);
+    assert_eq!(yield_now.as_mut().poll(&mut context), Poll::Ready(()));
+}
+
+#[test_case]
+fn poll_budget_is_unlimited_outside_of_tasks() {
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+    for _ in 0..task::POLL_BUDGET * 2 {
+        assert_eq!(task::poll_budget(&mut context), Poll::Ready(()));
+    }
+}
+
+#[test_case]
+fn priorities_are_ordered() {
+    assert_eq!(Priority::default(), Priority::Normal);
+    assert!(Priority::High < Priority::Normal && Priority::Normal < Priority::Low);
+}
+
+// sets its flag when the future owning it is dropped
+struct DropFlag(&'static AtomicBool);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+struct CountingWaker {
+    wakes: AtomicU32,
+}
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.wakes.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test_case]
+fn join_handle_resolves_to_the_output() {
+    let handle = task::spawn(async { 6 * 7 });
+    assert_eq!(Executor::new().block_on(handle), Ok(42));
+}
+
+#[test_case]
+fn spawn_works_from_inside_a_task() {
+    let handle = task::spawn(async { task::spawn(async { "inner" }).await });
+    assert_eq!(Executor::new().block_on(handle), Ok(Ok("inner")));
+}
+
+#[test_case]
+fn abort_drops_the_future() {
+    static DROPPED: AtomicBool = AtomicBool::new(false);
+    let mut executor = Executor::new();
+    let handle = task::spawn(async {
+        let _flag = DropFlag(&DROPPED);
+        future::pending::<()>().await;
+    });
+    // let the task start waiting
+    executor.block_on(task::yield_now());
+    assert!(!DROPPED.load(Ordering::SeqCst));
+    assert!(!handle.is_finished());
+
+    handle.abort();
+    assert_eq!(executor.block_on(handle), Err(JoinError::Aborted));
+    assert!(DROPPED.load(Ordering::SeqCst));
+}
+
+#[test_case]
+fn aborted_task_releases_the_join_waker() {
+    let mut executor = Executor::new();
+    let mut handle = task::spawn(future::pending::<()>());
+    let counting_waker = Arc::new(CountingWaker { wakes: AtomicU32::new(0) });
+    let waker = Waker::from(counting_waker.clone());
+    assert_eq!(Pin::new(&mut handle).poll(&mut Context::from_waker(&waker)), Poll::Pending);
+    // the join state keeps a clone until the task finishes
+    assert_eq!(Arc::strong_count(&counting_waker), 3);
+
+    handle.abort();
+    executor.block_on(async {
+        while counting_waker.wakes.load(Ordering::SeqCst) == 0 {
+            task::yield_now().await;
+        }
+    });
+    assert_eq!(counting_waker.wakes.load(Ordering::SeqCst), 1);
+    assert_eq!(Arc::strong_count(&counting_waker), 2);
+    drop(handle);
+    drop(waker);
+    assert_eq!(Arc::strong_count(&counting_waker), 1);
+}
+
+// orders events across tasks
+static STEP: AtomicU64 = AtomicU64::new(0);
+
+fn next_step() -> u64 {
+    STEP.fetch_add(1, Ordering::SeqCst)
+}
+
+// takes `2 * POLL_BUDGET` units of budget, so it is polled at least twice
+async fn use_up_budget_twice() {
+    for _ in 0..task::POLL_BUDGET * 2 {
+        future::poll_fn(task::poll_budget).await;
+    }
+}
+
+#[test_case]
+fn task_out_of_budget_yields_to_a_lower_class() {
+    // with more CPUs the tasks may run in parallel and in any order
+    if smp::cpu_count() > 1 {
+        return;
+    }
+    let mut executor = Executor::new();
+    let chatty = task::spawn_with_priority(Priority::High, async {
+        let started = next_step();
+        use_up_budget_twice().await;
+        (started, next_step())
+    });
+    let quiet = task::spawn_with_priority(Priority::Normal, async { next_step() });
+    let (chatty, quiet) = executor.block_on(async { (chatty.await, quiet.await) });
+    let (chatty_started, chatty_finished) = chatty.expect("Chatty task failed");
+    let quiet = quiet.expect("Quiet task failed");
+    // without demotion the high priority task would run to completion first
+    assert!(chatty_started < quiet);
+    assert!(quiet < chatty_finished);
+}
+
+#[test_case]
+fn task_stats_count_polls_time_and_budget_yields() {
+    let mut executor = Executor::new();
+    let handle = task::spawn_with_priority(Priority::High, async {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(1) {
+            core::hint::spin_loop();
+        }
+        use_up_budget_twice().await;
+        // the executor accounts a poll once it returned, so this sees the first one only
+        task::task_stats(task::current_task_id().expect("Not in a task")).expect("Missing task stats")
+    });
+    let task_id = handle.id();
+    let stats = executor.block_on(handle).expect("Task failed");
+    assert_eq!(stats.priority, Priority::High);
+    assert_eq!(stats.polls, 1);
+    assert_eq!(stats.budget_yields, 1);
+    assert!(stats.run_time >= Duration::from_millis(1));
+    // the stats are gone with the task
+    assert!(task::task_stats(task_id).is_none());
+}
+
+#[test_case]
+fn lower_class_runs_within_the_starvation_interval() {
+    static LOW_RAN: AtomicBool = AtomicBool::new(false);
+    let mut executor = Executor::new();
+    let high = task::spawn_with_priority(Priority::High, async {
+        let mut yields = 0;
+        while !LOW_RAN.load(Ordering::SeqCst) && yields < STARVATION_INTERVAL * 4 {
+            yields += 1;
+            task::yield_now().await;
+        }
+        yields
+    });
+    let low = task::spawn_with_priority(Priority::Low, async { LOW_RAN.store(true, Ordering::SeqCst) });
+    let (high, low) = executor.block_on(async { (high.await, low.await) });
+    assert_eq!(low, Ok(()));
+    assert!(high.expect("High priority task failed") <= STARVATION_INTERVAL);
+}
@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::time::Duration;
+use bootloader_api::{ entry_point, BootInfo };
+use kernel::time::{ self, DateTime, Instant };
+
+entry_point!(main, config = &kernel::BOOTLOADER_CONFIG);
+
+fn main(boot_info: &'static mut BootInfo) -> ! {
+    kernel::init(boot_info);
+    test_main();
+    kernel::interrupts::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn unix_epoch() {
+    let epoch = DateTime::from_unix_time(Duration::ZERO);
+    assert_eq!((epoch.year, epoch.month, epoch.day, epoch.hour, epoch.minute, epoch.second), (1970, 1, 1, 0, 0, 0));
+    assert_eq!(epoch.weekday(), 4);
+}
+
+#[test_case]
+fn leap_day_round_trip() {
+    let leap_day = DateTime { year: 2024, month: 2, day: 29, hour: 23, minute: 59, second: 59, nanosecond: 0 };
+    assert_eq!(leap_day.unix_timestamp(), 1_709_251_199);
+    assert_eq!(DateTime::from_unix_time(leap_day.unix_time()), leap_day);
+}
+
+#[test_case]
+fn invalid_fields_saturate() {
+    let garbage = DateTime { year: 1900, month: 0, day: 0, hour: 0, minute: 0, second: 0, nanosecond: 0 };
+    assert!(!garbage.is_valid());
+    assert_eq!(garbage.unix_timestamp(), 0);
+    let garbage = DateTime { year: 2024, month: 13, day: 45, hour: 0, minute: 0, second: 0, nanosecond: 0 };
+    // 2024-12-31, day 31 is the highest one accepted
+    assert_eq!(garbage.unix_timestamp(), 1_735_603_200);
+}
+
+#[test_case]
+fn wall_clock_is_set() {
+    let now = time::wall_clock().expect("Wall clock was not initialized");
+    assert!(now.year >= 2000);
+    assert!((1..=12).contains(&now.month) && (1..=31).contains(&now.day));
+}
+
+#[test_case]
+fn uptime_advances() {
+    let start = time::uptime();
+    while time::uptime() < start + Duration::from_millis(10) {
+        x86_64::instructions::hlt();
+    }
+    assert!(time::ticks() > 0);
+}
+
+#[test_case]
+fn instant_is_monotonic() {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(5) {
+        x86_64::instructions::hlt();
+    }
+    let end = Instant::now();
+    assert!(end > start);
+    assert_eq!(start + (end - start), end);
+    assert_eq!(start.duration_since(end), Duration::ZERO);
+}
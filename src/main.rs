@@ -1,10 +1,274 @@
+use std::fs::File;
+use std::path::{ Path, PathBuf };
+use std::process::{ self, Command, ExitStatus };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+// the kernel writes 0x10 to the isa-debug-exit device on success and QEMU exits with `(code << 1) | 1`
+const QEMU_SUCCESS_EXIT_CODE: i32 = (0x10 << 1) | 1;
+// test binaries that neither pass nor fail within this time are considered hung
+const DEFAULT_TEST_TIMEOUT: Duration = Duration::from_secs(60);
+const TIMEOUT_EXIT_CODE: i32 = 124;
+// test binaries that need application processors, by the name cargo gives them in front of the hash
+const SMP_TESTS: &[&str] = &["smp"];
+const SMP_TEST_CPUS: u32 = 4;
+
+const USAGE: &str = "\
+Usage: hexand [OPTIONS] [KERNEL]
+
+Boots Hexand in QEMU. When KERNEL is given (e.g. by `cargo test` using hexand as runner)
+that kernel binary is booted instead, implying --test --headless --serial. The
+multiprocessor tests get 4 CPUs unless --cpus is given.
+
+Options:
+    --boot <uefi|bios>   firmware to boot with, defaults to uefi
+    --cmdline <OPTIONS>  kernel command line, replaces the one from initrd/cmdline
+    --headless           run without a display window
+    --serial             connect the guest serial port to stdio
+    --memory <SIZE>      guest memory size, e.g. 512M or 2G
+    --cpus <COUNT>       number of guest CPUs
+    --drive <FILE>       attach an extra raw drive, can be repeated
+    --gdb                start a GDB server on port 1234 and wait for the debugger
+    --test               map the isa-debug-exit code to the exit status
+    --timeout <SECONDS>  kill the guest if it is still running after this time
+    -h, --help           print this help";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Firmware {
+    #[default]
+    Uefi,
+    Bios,
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    kernel: Option<PathBuf>,
+    firmware: Firmware,
+    cmdline: Option<String>,
+    headless: bool,
+    serial: bool,
+    memory: Option<String>,
+    cpus: Option<u32>,
+    drives: Vec<PathBuf>,
+    gdb: bool,
+    test: bool,
+    timeout: Option<Duration>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {name}"));
+            match arg.as_str() {
+                "--boot" => {
+                    options.firmware = match value("--boot")?.as_str() {
+                        "uefi" => Firmware::Uefi,
+                        "bios" => Firmware::Bios,
+                        firmware => {
+                            return Err(format!("Unknown firmware: {firmware}"));
+                        }
+                    };
+                }
+                "--cmdline" => {
+                    options.cmdline = Some(value("--cmdline")?);
+                }
+                "--headless" => {
+                    options.headless = true;
+                }
+                "--serial" => {
+                    options.serial = true;
+                }
+                "--memory" => {
+                    options.memory = Some(value("--memory")?);
+                }
+                "--cpus" => {
+                    let cpus = value("--cpus")?;
+                    options.cpus = Some(cpus.parse().map_err(|_| format!("Invalid CPU count: {cpus}"))?);
+                }
+                "--drive" => {
+                    options.drives.push(PathBuf::from(value("--drive")?));
+                }
+                "--gdb" => {
+                    options.gdb = true;
+                }
+                "--test" => {
+                    options.test = true;
+                }
+                "--timeout" => {
+                    let seconds = value("--timeout")?;
+                    let seconds = seconds.parse().map_err(|_| format!("Invalid timeout: {seconds}"))?;
+                    options.timeout = Some(Duration::from_secs(seconds));
+                }
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    process::exit(0);
+                }
+                option if option.starts_with('-') => {
+                    return Err(format!("Unknown option: {option}"));
+                }
+                kernel => {
+                    if options.kernel.replace(PathBuf::from(kernel)).is_some() {
+                        return Err(String::from("Only one kernel can be booted"));
+                    }
+                }
+            }
+        }
+        if let Some(kernel) = &options.kernel {
+            options.test = true;
+            options.headless = true;
+            options.serial = true;
+            if options.cpus.is_none() && is_smp_test(kernel) {
+                options.cpus = Some(SMP_TEST_CPUS);
+            }
+        }
+        if options.test && options.timeout.is_none() && !options.gdb {
+            options.timeout = Some(DEFAULT_TEST_TIMEOUT);
+        }
+        Ok(options)
+    }
+}
+
 fn main() {
-    let uefi_path = env!("UEFI_PATH");
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|error| {
+        eprintln!("{error}\n\n{USAGE}");
+        process::exit(2);
+    });
+
+    // the prebuilt images can only be used with the default kernel and command line
+    let image_path = match (&options.kernel, &options.cmdline, options.firmware) {
+        (None, None, Firmware::Uefi) => PathBuf::from(env!("UEFI_PATH")),
+        (None, None, Firmware::Bios) => PathBuf::from(env!("BIOS_PATH")),
+        (kernel, cmdline, firmware) => {
+            let kernel = kernel.as_deref().unwrap_or(Path::new(env!("KERNEL_PATH")));
+            let ramdisk_path = match cmdline {
+                Some(cmdline) => create_ramdisk(kernel, cmdline),
+                None => PathBuf::from(env!("RAMDISK_PATH")),
+            };
+            create_disk_image(kernel, &ramdisk_path, firmware)
+        }
+    };
+
+    let mut cmd = Command::new("qemu-system-x86_64");
+    // without `-bios` QEMU boots with its default SeaBIOS firmware
+    if options.firmware == Firmware::Uefi {
+        cmd.arg("-bios").arg(ovmf_prebuilt::ovmf_pure_efi());
+    }
+    cmd.arg("-drive").arg(format!("format=raw,file={}", image_path.display()));
+    for drive in &options.drives {
+        cmd.arg("-drive").arg(format!("format=raw,file={}", drive.display()));
+    }
+    if let Some(memory) = &options.memory {
+        cmd.arg("-m").arg(memory);
+    }
+    if let Some(cpus) = options.cpus {
+        cmd.arg("-smp").arg(cpus.to_string());
+    }
+    if options.headless {
+        cmd.arg("-display").arg("none");
+    }
+    if options.serial {
+        cmd.arg("-serial").arg("stdio");
+    }
+    if options.gdb {
+        cmd.arg("-s").arg("-S");
+    }
+    if options.test {
+        cmd.arg("-device").arg("isa-debug-exit,iobase=0xf4,iosize=0x04");
+    }
+
+    let status = match wait_for_qemu(&mut cmd, options.timeout) {
+        Some(status) => status,
+        None => {
+            eprintln!("QEMU timed out after {:?}, killed the guest", options.timeout.unwrap());
+            process::exit(TIMEOUT_EXIT_CODE);
+        }
+    };
+
+    if options.test {
+        match status.code() {
+            Some(QEMU_SUCCESS_EXIT_CODE) => process::exit(0),
+            code => {
+                let kernel = options.kernel.as_deref().unwrap_or(Path::new("kernel"));
+                eprintln!("Test {} failed with QEMU exit code {code:?}", kernel.display());
+                process::exit(1);
+            }
+        }
+    }
+    process::exit(status.code().unwrap_or(1));
+}
+
+// `cargo test` names test binaries `<test>-<hash>`
+fn is_smp_test(kernel: &Path) -> bool {
+    let name = kernel.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let test = name.rsplit_once('-').map_or(name, |(test, _)| test);
+    SMP_TESTS.contains(&test)
+}
+
+// copy the ramdisk built from `initrd` with its `cmdline` file replaced, next to the kernel binary
+fn create_ramdisk(kernel: &Path, cmdline: &str) -> PathBuf {
+    let ramdisk_path = kernel.with_extension("initrd.tar");
+    let mut original = tar::Archive::new(File::open(env!("RAMDISK_PATH")).expect("Failed to open ramdisk"));
+    let mut builder = tar::Builder::new(File::create(&ramdisk_path).expect("Failed to create ramdisk"));
+    for entry in original.entries().expect("Failed to read ramdisk") {
+        let entry = entry.expect("Failed to read ramdisk entry");
+        let path = entry.path().expect("Invalid path in ramdisk").into_owned();
+        if path.strip_prefix(".").unwrap_or(&path) == Path::new("cmdline") {
+            continue;
+        }
+        let header = entry.header().clone();
+        builder.append(&header, entry).expect("Failed to copy ramdisk entry");
+    }
+    let mut header = tar::Header::new_ustar();
+    header.set_size(cmdline.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, "cmdline", cmdline.as_bytes()).expect("Failed to add cmdline to ramdisk");
+    builder.finish().expect("Failed to write ramdisk");
+    ramdisk_path
+}
 
-    let mut cmd = std::process::Command::new("qemu-system-x86_64");
-    cmd.arg("-bios").arg(ovmf_prebuilt::ovmf_pure_efi());
-    cmd.arg("-drive").arg(format!("format=raw,file={uefi_path}"));
+// disk images for kernels passed on the command line are created next to the kernel binary
+fn create_disk_image(kernel: &Path, ramdisk_path: &Path, firmware: Firmware) -> PathBuf {
+    match firmware {
+        Firmware::Uefi => {
+            let uefi_path = kernel.with_extension("uefi.img");
+            bootloader::UefiBoot::new(kernel)
+                .set_ramdisk(ramdisk_path)
+                .create_disk_image(&uefi_path)
+                .expect("Failed to create UEFI disk image");
+            uefi_path
+        }
+        Firmware::Bios => {
+            let bios_path = kernel.with_extension("bios.img");
+            bootloader::BiosBoot::new(kernel)
+                .set_ramdisk(ramdisk_path)
+                .create_disk_image(&bios_path)
+                .expect("Failed to create BIOS disk image");
+            bios_path
+        }
+    }
+}
 
-    let mut child = cmd.spawn().unwrap();
-    child.wait().unwrap();
-}
\ No newline at end of file
+// returns `None` if QEMU was killed because it did not exit before the timeout
+fn wait_for_qemu(cmd: &mut Command, timeout: Option<Duration>) -> Option<ExitStatus> {
+    let mut child = cmd.spawn().expect("Failed to start QEMU");
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => {
+            return Some(child.wait().expect("Failed to wait for QEMU"));
+        }
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait().expect("Failed to wait for QEMU") {
+            return Some(status);
+        }
+        if Instant::now() >= deadline {
+            child.kill().expect("Failed to kill QEMU");
+            child.wait().expect("Failed to wait for QEMU");
+            return None;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}